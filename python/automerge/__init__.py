@@ -5,36 +5,138 @@ from ._backend import (
     Document,
     Mapping,
     Sequence,
+    DocumentTransaction,
+    MappingTransaction,
+    SequenceTransaction,
+    TextTransaction,
     transaction,
+    transact,
     entries,
     init,
     load,
+    load_json,
+    load_incremental,
     save,
+    save_incremental,
+    save_to_file,
+    load_from_file,
+    to_json,
     fork,
+    fork_at,
+    at,
     merge,
+    merged,
     Change,
     apply_changes,
+    ApplyResult,
+    get_missing_deps,
+    get_pending_changes,
     get_last_local_change,
+    get_heads,
+    get_changes,
+    get_local_changes,
+    history,
+    HistoryEntry,
+    ModifiedInfo,
+    TextSpan,
+    BlockMarker,
+    get_changes_added,
+    get_change_by_hash,
+    get_actor,
+    set_actor,
+    set_read_behavior,
+    TransactionWaitTimeout,
+    AutomergeBaseError,
+    TransactionError,
+    TransactionInProgressError,
+    TransactionReusedError,
+    NestedTransactionError,
+    HistoricalViewError,
+    LoadError,
+    StaleObjectError,
     Counter,
     Text,
+    TextView,
+    Cursor,
+    ChangeHash,
+    Unknown,
+    SyncState,
+    generate_sync_message,
+    receive_sync_message,
+    timestamp_millis,
+    raw_timestamps,
+    Timestamp,
+    Patch,
+    diff,
 )
 
 __all__ = [
     "Document",
     "Mapping",
     "Sequence",
+    "DocumentTransaction",
+    "MappingTransaction",
+    "SequenceTransaction",
+    "TextTransaction",
     "transaction",
+    "transact",
     "entries",
     "init",
     "load",
+    "load_json",
+    "load_incremental",
     "save",
+    "save_incremental",
+    "save_to_file",
+    "load_from_file",
+    "to_json",
     "fork",
+    "fork_at",
+    "at",
     "merge",
+    "merged",
     "Change",
     "apply_changes",
+    "ApplyResult",
+    "get_missing_deps",
+    "get_pending_changes",
     "get_last_local_change",
+    "get_heads",
+    "get_changes",
+    "get_local_changes",
+    "history",
+    "HistoryEntry",
+    "ModifiedInfo",
+    "TextSpan",
+    "BlockMarker",
+    "get_changes_added",
+    "get_change_by_hash",
+    "get_actor",
+    "set_actor",
+    "set_read_behavior",
+    "TransactionWaitTimeout",
+    "AutomergeBaseError",
+    "TransactionError",
+    "TransactionInProgressError",
+    "TransactionReusedError",
+    "NestedTransactionError",
+    "HistoricalViewError",
+    "LoadError",
+    "StaleObjectError",
     "Counter",
     "Text",
+    "TextView",
+    "Cursor",
+    "ChangeHash",
+    "Unknown",
+    "SyncState",
+    "generate_sync_message",
+    "receive_sync_message",
+    "timestamp_millis",
+    "raw_timestamps",
+    "Timestamp",
+    "Patch",
+    "diff",
 ]
 
 
@@ -46,7 +148,7 @@ def dump(doc: Document):
                 value = dump(value)
             elif isinstance(value, Counter):
                 value = value.get()
-            elif isinstance(value, Text):
+            elif isinstance(value, (Text, TextView)):
                 value = str(value)
             res[name] = value
     else:  # sequence
@@ -56,7 +158,7 @@ def dump(doc: Document):
                 value = dump(value)
             elif isinstance(value, Counter):
                 value = value.get()
-            elif isinstance(value, Text):
+            elif isinstance(value, (Text, TextView)):
                 value = str(value)
             res.append(value)
 
@@ -1,15 +1,160 @@
 #!/usr/bin/env python3
 
+import datetime
+import os
+import typing
 from typing import *
 
 T = TypeVar("T")
 
+HashArg = Union["ChangeHash", bytes, str]
+ActorArg = Union[bytes, str]
+CommitTime = Union[datetime.datetime, int]
+
 class Document(Generic[T]): ...
 
-def init(type: Type[T]) -> Document[T]: ...
+# Proxies compare equal by content (to other proxies and to dicts or lists), but hash by the object
+# they point to (document and obj_id), so equal proxies of different objects (e.g. in a fork) hash differently
+class Mapping(Document[T]):
+    obj_id: str
+    def __eq__(self, other: object) -> bool: ...
+    def __hash__(self) -> int: ...
+    def modified_info(self, name: str) -> List["ModifiedInfo"]: ...
+
+class Sequence(Document[T]):
+    obj_id: str
+    def __eq__(self, other: object) -> bool: ...
+    def __hash__(self) -> int: ...
+    def modified_info(self, index: int) -> List["ModifiedInfo"]: ...
+
+def init(initial: Optional[T] = None, message: Optional[str] = None) -> Document[T]: ...
 
 class DocumentTransaction(Generic[T]):
     def __enter__(self) -> T: ...
     def __exit__(self, _, __, ___): ...
 
-def transaction(doc: Document[T], message: str) -> DocumentTransaction[T]: ...
+def transaction(
+    doc: Document[T],
+    message: Optional[str] = None,
+    time: Optional[CommitTime] = None,
+    actor: Optional[ActorArg] = None,
+    snapshot: bool = False,
+) -> DocumentTransaction[T]: ...
+def transact(
+    doc: Document[T],
+    func: Callable[[T], Any],
+    message: Optional[str] = None,
+    time: Optional[CommitTime] = None,
+    actor: Optional[ActorArg] = None,
+    snapshot: bool = False,
+) -> Tuple[Any, Optional["ChangeHash"]]: ...
+
+class TextView:
+    def marks(self) -> List[Tuple[int, int, str, Any]]: ...
+    def spans(self) -> List[Union["TextSpan", "BlockMarker"]]: ...
+
+class TextTransaction(DocumentTransaction[Any]):
+    def mark(self, start: int, end: int, name: str, value: Any, expand: str = "grow") -> None: ...
+    def unmark(self, start: int, end: int, name: str, expand: str = "grow") -> None: ...
+    def split_block(self, index: int, attrs: Optional[typing.Mapping[str, Any]] = None) -> None: ...
+    def update_block(self, index: int, attrs: typing.Mapping[str, Any]) -> None: ...
+    def join_block(self, index: int) -> None: ...
+    def spans(self) -> List[Union["TextSpan", "BlockMarker"]]: ...
+
+class TextSpan:
+    text: str
+    marks: Dict[str, Any]
+
+class BlockMarker:
+    attrs: Dict[str, Any]
+
+class Timestamp:
+    millis: int
+    def __init__(self, millis: int) -> None: ...
+
+class RawTimestamps:
+    def __enter__(self) -> "RawTimestamps": ...
+    def __exit__(self, _, __, ___) -> None: ...
+
+def raw_timestamps() -> RawTimestamps: ...
+def timestamp_millis(datetime: Union[datetime.datetime, Timestamp]) -> int: ...
+
+class ChangeHash:
+    def __init__(self, hash: HashArg) -> None: ...
+    def __bytes__(self) -> bytes: ...
+    def hex(self) -> str: ...
+    def __lt__(self, other: Union["ChangeHash", bytes]) -> bool: ...
+    def __hash__(self) -> int: ...
+
+class Change:
+    def __init__(self, bytes: bytes) -> None: ...
+    def bytes(self) -> bytes: ...
+    def to_json(self) -> str: ...
+
+class Patch:
+    action: str
+    path: List[Union[str, int]]
+    key: Union[str, int, None]
+    value: Any
+    length: int
+
+class ApplyResult:
+    applied: int
+    pending: List[ChangeHash]
+
+class HistoryEntry:
+    hash: ChangeHash
+    actor_id: bytes
+    seq: int
+    message: Optional[str]
+    timestamp: int
+    operations: int
+
+class ModifiedInfo:
+    opid: str
+    actor_id: bytes
+    hash: Optional[ChangeHash]
+    message: Optional[str]
+    timestamp: Optional[int]
+
+def fork(doc: Document[T], actor: Optional[ActorArg] = None) -> Document[T]: ...
+def at(doc: Document[T], heads: List[HashArg]) -> Document[T]: ...
+def fork_at(doc: Document[T], heads: List[HashArg], actor: Optional[ActorArg] = None) -> Document[T]: ...
+def merge(doc_a: Document[T], doc_b: Document[Any]) -> List[Patch]: ...
+def merged(doc_a: Document[T], doc_b: Document[Any]) -> Document[T]: ...
+def diff(doc: Document[Any], before: List[HashArg], after: List[HashArg]) -> List[Patch]: ...
+def save(doc: Document[Any]) -> bytes: ...
+def save_incremental(doc: Document[Any]) -> bytes: ...
+def save_to_file(doc: Document[Any], target: Union[str, os.PathLike, BinaryIO]) -> None: ...
+def load(bytes: bytes) -> Document[Any]: ...
+def load_incremental(doc: Document[Any], bytes: bytes) -> int: ...
+def load_from_file(source: Union[str, os.PathLike, BinaryIO]) -> Document[Any]: ...
+def to_json(doc: Document[Any], heads: Optional[List[HashArg]] = None) -> str: ...
+def load_json(json: str) -> Document[Any]: ...
+def apply_changes(
+    doc: Document[Any],
+    changes: Union[Change, bytes, Iterable[Union[Change, bytes]]],
+    on_patch: Optional[Callable[[Patch], Any]] = None,
+) -> ApplyResult: ...
+def get_heads(doc: Document[Any]) -> List[ChangeHash]: ...
+def get_pending_changes(doc: Document[Any]) -> List[ChangeHash]: ...
+def get_missing_deps(doc: Document[Any], heads: Optional[List[HashArg]] = None) -> List[ChangeHash]: ...
+def get_last_local_change(doc: Document[Any]) -> Optional[Change]: ...
+def get_changes(doc: Document[Any], heads: List[HashArg]) -> List[Change]: ...
+def get_local_changes(doc: Document[Any], since_heads: List[HashArg]) -> List[Change]: ...
+def get_changes_added(doc_a: Document[Any], doc_b: Document[Any]) -> List[Change]: ...
+def get_change_by_hash(doc: Document[Any], hash: HashArg) -> Optional[Change]: ...
+def history(doc: Document[Any]) -> Iterator[HistoryEntry]: ...
+def get_actor(doc: Document[Any]) -> bytes: ...
+def set_actor(doc: Document[Any], actor: ActorArg) -> None: ...
+def set_read_behavior(doc: Document[Any], behavior: str, timeout: Optional[float] = None) -> None: ...
+
+class TransactionWaitTimeout(TimeoutError): ...
+class AutomergeBaseError(ValueError): ...
+class TransactionError(AutomergeBaseError): ...
+class TransactionInProgressError(TransactionError): ...
+class TransactionReusedError(TransactionError): ...
+class NestedTransactionError(TransactionError): ...
+class HistoricalViewError(AutomergeBaseError): ...
+class StaleObjectError(AutomergeBaseError): ...
+class LoadError(AutomergeBaseError): ...
@@ -1,66 +1,275 @@
-use std::sync::{Arc, Mutex};
+use std::cell::Cell;
+use std::sync::{
+    Arc, Condvar, LockResult, Mutex, MutexGuard, RwLock, RwLockWriteGuard, TryLockError,
+};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
 
 use automerge::{
+    hydrate,
+    iter::Span,
+    marks::{ExpandMark, Mark},
+    patches::TextRepresentation,
+    sync::SyncDoc,
     transaction::{CommitOptions, Transactable, Transaction},
-    Automerge, ChangeHash, ObjId, ObjType, Prop, ReadDoc, ScalarValue, Value,
+    ActorId, Automerge, ChangeHash, ObjId, ObjType, PatchLog, Prop, ReadDoc, ScalarValue,
+    TextEncoding, Value,
+};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use pyo3::buffer::PyBuffer;
+use pyo3::exceptions::{
+    PyAttributeError, PyIndexError, PyKeyError, PyOSError, PyOverflowError, PyTypeError,
+    PyValueError,
 };
-use pyo3::exceptions::{PyException, PyIndexError, PyTypeError, PyValueError};
-use pyo3::types::{PyBytes, PyMapping, PySequence, PySlice};
-use pyo3::{prelude::*, AsPyPointer};
-use std::convert::TryInto;
+use pyo3::types::{
+    timezone_utc, PyByteArray, PyBytes, PyDateTime, PyDelta, PyDeltaAccess, PyDict, PyFrozenSet,
+    PyList, PyLong, PyMapping, PySequence, PySet, PySlice, PyString, PyTuple, PyType,
+    PyTzInfoAccess,
+};
+use pyo3::{prelude::*, pyclass::CompareOp};
+use serde_json::{json, Value as JsonValue};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::convert::{TryFrom, TryInto};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
 use tracing;
 use tracing_subscriber;
 
 // The document type
 // This has shared ownership between all instances of Documents with the same underlying Automerge Document.
 // The python Document can refer to any of the Maps or Lists inside the Automerge Document
-// Mutex is needed because we support multithreading from the python side
-// The Option is needed to be able to move the Automerge Document into the struct holding the transaction
-// (as the transaction needs a mutable reference to the document)
-type AutomergeDocument = Arc<Mutex<Option<Automerge>>>;
+// The lock is needed because we support multithreading from the python side,
+// reads only take a shared lock so they can happen concurrently
+type AutomergeDocument = Arc<RwLock<DocumentState>>;
+
+// The state shared between all instances of Documents with the same underlying Automerge Document
+#[derive(Debug)]
+struct DocumentState {
+    // The Option is needed to be able to move the Automerge Document into the struct holding the transaction
+    // (as the transaction needs a mutable reference to the document)
+    doc: Option<Automerge>,
+    // The heads at the time of the last save, everything after these is returned by save_incremental
+    save_cursor: Vec<ChangeHash>,
+    // Changes passed to apply_changes which could not be applied yet, because of missing dependencies.
+    // They can also be applied by merge, load_incremental or sync, so this is only read through pending_changes
+    pending: Vec<ChangeHash>,
+    // A copy of the document as it was before the currently open transaction,
+    // so the document can still be read while the transaction owns it
+    snapshot: Option<Automerge>,
+    // Where the currently open transaction was opened (and its commit message), for the NestedTransaction error
+    open_transaction: Option<String>,
+    // The thread which opened the currently open transaction, it can't wait for its own transaction
+    transaction_thread: Option<ThreadId>,
+    // What happens when the document is used while a transaction owns it (and there is no snapshot)
+    read_behavior: ReadBehavior,
+    // Counts the finished transactions, used to wake up threads waiting for the open transaction
+    transactions_finished: Arc<(Mutex<u64>, Condvar)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ReadBehavior {
+    // raise UsingDocDuringTransaction
+    Fail,
+    // wait until the transaction is commited or rolled back, raising TransactionWaitTimeout after the timeout
+    Wait(Option<Duration>),
+}
+
+pyo3::create_exception!(
+    _backend,
+    TransactionWaitTimeout,
+    pyo3::exceptions::PyTimeoutError,
+    "Raised when waiting for the open transaction of a document timed out (see set_read_behavior)"
+);
+
+// Locks the document state (with lock) once available returns true for it.
+// While a transaction owns the document, this fails or waits depending on the read behavior of the document
+fn lock_document<'a, G: std::ops::Deref<Target = DocumentState>>(
+    automerge: &'a AutomergeDocument,
+    lock: impl Fn(&'a AutomergeDocument) -> LockResult<G>,
+    available: impl Fn(&DocumentState) -> bool,
+) -> PyResult<G> {
+    let mut deadline = None;
+    loop {
+        let state = lock(automerge).map_err(|_| AutomergeError::PoisonedDocument)?;
+        if available(&state) {
+            return Ok(state);
+        }
+        let ReadBehavior::Wait(timeout) = state.read_behavior else {
+            Err(AutomergeError::UsingDocDuringTransaction)?
+        };
+        if state.owned_by_current_thread() {
+            Err(AutomergeError::UsingDocDuringTransaction)?
+        }
+        let deadline =
+            *deadline.get_or_insert_with(|| timeout.map(|timeout| Instant::now() + timeout));
+        // the transaction can only finish once the state is unlocked, so no wake up can be missed
+        let waiter = state.transactions_finished.clone();
+        let finished = *waiter.0.lock().unwrap();
+        drop(state);
+        let timed_out = Python::with_gil(|py| {
+            py.allow_threads(|| {
+                let (count, condvar) = &*waiter;
+                let mut count = count.lock().unwrap();
+                while *count == finished {
+                    match deadline {
+                        Some(deadline) => {
+                            let now = Instant::now();
+                            if now >= deadline {
+                                return true;
+                            }
+                            count = condvar.wait_timeout(count, deadline - now).unwrap().0;
+                        }
+                        None => count = condvar.wait(count).unwrap(),
+                    }
+                }
+                false
+            })
+        });
+        if timed_out {
+            return Err(TransactionWaitTimeout::new_err(
+                "timed out waiting for the transaction of the document to finish",
+            ));
+        }
+    }
+}
+
+impl DocumentState {
+    fn new(doc: Automerge) -> Self {
+        Self {
+            doc: Some(doc),
+            save_cursor: Vec::new(),
+            pending: Vec::new(),
+            snapshot: None,
+            open_transaction: None,
+            transaction_thread: None,
+            read_behavior: ReadBehavior::Fail,
+            transactions_finished: Arc::new((Mutex::new(0), Condvar::new())),
+        }
+    }
+
+    // whether the open transaction was opened by this thread, which would wait for itself forever
+    fn owned_by_current_thread(&self) -> bool {
+        self.transaction_thread == Some(thread::current().id())
+    }
+
+    // the state of a document which was loaded or forked, its changes are already stored elsewhere,
+    // so save_incremental only returns the ones made after this
+    fn loaded(doc: Automerge) -> Self {
+        Self {
+            save_cursor: doc.get_heads(),
+            ..Self::new(doc)
+        }
+    }
+
+    fn save(&mut self) -> Result<Vec<u8>, AutomergeError> {
+        let doc = self
+            .doc
+            .as_ref()
+            .ok_or(AutomergeError::UsingDocDuringTransaction)?;
+        self.save_cursor = doc.get_heads();
+        Ok(doc.save())
+    }
+
+    fn save_incremental(&mut self) -> Result<Vec<u8>, AutomergeError> {
+        let doc = self
+            .doc
+            .as_ref()
+            .ok_or(AutomergeError::UsingDocDuringTransaction)?;
+        let bytes = doc.save_after(&self.save_cursor);
+        if !bytes.is_empty() {
+            self.save_cursor = doc.get_heads();
+        }
+        Ok(bytes)
+    }
+
+    // the changes passed to apply_changes which are still waiting for their dependencies
+    fn pending_changes(&self) -> Vec<ChangeHash> {
+        let Some(doc) = self.doc.as_ref() else {
+            return self.pending.clone();
+        };
+        self.pending
+            .iter()
+            .copied()
+            .filter(|hash| doc.get_change_by_hash(hash).is_none())
+            .collect()
+    }
+
+    // applies the changes, returning how many of them (including the ones pending from previous calls)
+    // were applied (also when applying failed part way through) and which are still waiting for their dependencies
+    fn apply_changes(
+        &mut self,
+        changes: Vec<automerge::Change>,
+        patch_log: &mut PatchLog,
+    ) -> (usize, Result<Vec<ChangeHash>, AutomergeError>) {
+        let mut candidates = self.pending_changes();
+        let Some(doc) = self.doc.as_mut() else {
+            return (0, Err(AutomergeError::UsingDocDuringTransaction));
+        };
+        for change in &changes {
+            let hash = change.hash();
+            if doc.get_change_by_hash(&hash).is_none() && !candidates.contains(&hash) {
+                candidates.push(hash);
+            }
+        }
+        let result = doc.apply_changes_log_patches(changes, patch_log);
+        let (applied, pending): (Vec<_>, Vec<_>) = candidates
+            .into_iter()
+            .partition(|hash| doc.get_change_by_hash(hash).is_some());
+        self.pending = pending.clone();
+        (
+            applied.len(),
+            result
+                .map(|()| pending)
+                .map_err(AutomergeError::AutomergeError),
+        )
+    }
+}
 
 // the baseclass for the python bindings for a Automerge Document.
 // Each instance can refere to one of the Maps or Lists inside the Document
 // It provides access to the items or properties of that List or Map
 #[pyclass(subclass)]
+#[derive(Clone)]
 pub struct Document {
     obj_id: ObjId,
     automerge: AutomergeDocument,
+    // If set, this is a read only view of the document as it was at these heads
+    heads: Option<Vec<ChangeHash>>,
 }
 
 impl Document {
     fn from_doc(py: Python<'_>, doc: Automerge) -> PyResult<PyObject> {
-        Document::for_subfield_inner(
+        Document::from_state(py, DocumentState::new(doc))
+    }
+
+    // like from_doc, for documents which were loaded or forked (see DocumentState::loaded)
+    fn from_loaded_doc(py: Python<'_>, doc: Automerge) -> PyResult<PyObject> {
+        Document::from_state(py, DocumentState::loaded(doc))
+    }
+
+    fn from_state(py: Python<'_>, state: DocumentState) -> PyResult<PyObject> {
+        Document::for_subfield(
             py,
-            None,
-            Arc::new(Mutex::new(Some(doc))),
+            Arc::new(RwLock::new(state)),
             ObjType::Map,
             automerge::ROOT,
+            None,
         )
     }
 
     fn for_subfield(
         py: Python<'_>,
-        doc: &Automerge,
-        automerge: AutomergeDocument,
-        ty: ObjType,
-        obj_id: ObjId,
-    ) -> PyResult<PyObject> {
-        Document::for_subfield_inner(py, Some(doc), automerge, ty, obj_id)
-    }
-
-    fn for_subfield_inner(
-        py: Python<'_>,
-        // We only need this in the text case. This allows both from_doc and for_subfield to use this function
-        // from_doc will never hit the text case
-        document: Option<&Automerge>,
         automerge: AutomergeDocument,
         ty: ObjType,
         obj_id: ObjId,
+        heads: Option<Vec<ChangeHash>>,
     ) -> PyResult<PyObject> {
         let doc = Self {
-            obj_id: obj_id.clone(),
+            obj_id,
             automerge,
+            heads,
         };
         Ok(match ty {
             ObjType::Map | ObjType::Table => {
@@ -71,50 +280,244 @@ impl Document {
                 let init = PyClassInitializer::from(doc).add_subclass(Sequence);
                 PyCell::new(py, init)?.to_object(py)
             }
-            ObjType::Text => {
-                // TODO(robin): this feels a bit unclean
-                // maybe we want three text types or so?
-                // Text for input, Text when reading and Text for Transaction?
-                let document = document.unwrap();
-                PyCell::new(
-                    py,
-                    Text {
-                        text: document
-                            .text(obj_id.clone())
-                            .map_err(AutomergeError::AutomergeError)?,
-                    },
-                )?
-                .to_object(py)
-            }
+            // Text is only used to write text, reading it gives a live TextView
+            ObjType::Text => PyCell::new(py, TextView { document: doc })?.to_object(py),
         })
     }
 }
 
+fn is_dunder(name: &str) -> bool {
+    name.starts_with("__") && name.ends_with("__")
+}
+
 macro_rules! with_doc {
     ($self:ident, |$doc:ident| $func:tt) => {{
-        let automerge = $self.automerge.lock().unwrap();
-        let $doc = automerge
+        let automerge = lock_document(
+            &$self.automerge,
+            |automerge| automerge.read(),
+            |state| state.doc.is_some() || state.snapshot.is_some(),
+        )?;
+        let doc = automerge
+            .doc
             .as_ref()
+            .or(automerge.snapshot.as_ref())
             .ok_or(AutomergeError::UsingDocDuringTransaction)?;
+        let $doc = doc;
         $func
     }};
 }
 
 macro_rules! with_doc_mut {
     ($self:ident, |$doc:ident| $func:tt) => {{
-        let mut automerge = $self.automerge.lock().unwrap();
-        let $doc = automerge
+        if $self.heads.is_some() {
+            Err(AutomergeError::ModifyingHistoricalView)?
+        }
+        let mut automerge = lock_document(
+            &$self.automerge,
+            |automerge| automerge.write(),
+            |state| state.doc.is_some(),
+        )?;
+        let doc = automerge
+            .doc
             .as_mut()
             .ok_or(AutomergeError::UsingDocDuringTransaction)?;
+        let $doc = doc;
         $func
     }};
 }
 
+impl Document {
+    // raises StaleObjectError if the object was deleted, only called from the entry points of the
+    // proxies as walking the parents on every access is too expensive
+    fn ensure_live(&self) -> PyResult<()> {
+        if self.is_live()? {
+            Ok(())
+        } else {
+            Err(AutomergeError::StaleObject.into())
+        }
+    }
+
+    // describes where this object is for error messages (see location)
+    fn location(&self) -> String {
+        let location = || -> PyResult<String> {
+            with_doc!(self, |doc| {
+                Ok(location(doc, &self.obj_id, self.heads.as_deref()))
+            })
+        };
+        location().unwrap_or_default()
+    }
+
+    // reads the value of a key of a map, returns None if the key does not exist
+    fn get_key(&self, py: Python<'_>, name: &str) -> PyResult<Option<PyObject>> {
+        with_doc! {self, |doc| {
+            if get(doc, self.obj_id.clone(), name, self.heads.as_deref())?.is_none() {
+                return Ok(None);
+            }
+            read_value(py, doc, self.obj_id.clone(), name, self.heads.as_deref(), |ty, obj_id| {
+                Document::for_subfield(py, self.automerge.clone(), ty, obj_id, self.heads.clone())
+            }, Some(|value| BoundCounter::bind(py, self, name, value))).map(Some)
+        }}
+    }
+
+    // reads the elements from start to stop of this list from the already locked doc in one pass,
+    // nested objects are returned as proxies, or as plain python values if deep is set
+    fn read_range<T: ReadDoc>(
+        &self,
+        py: Python<'_>,
+        doc: &T,
+        start: usize,
+        stop: usize,
+        deep: bool,
+    ) -> PyResult<Vec<PyObject>> {
+        let heads = self.heads.as_deref();
+        match heads {
+            Some(heads) => doc.list_range_at(&self.obj_id, start..stop, heads),
+            None => doc.list_range(&self.obj_id, start..stop),
+        }
+        .map(|item| {
+            if deep {
+                materialize(py, doc, item.value, item.id, heads)
+            } else {
+                self.value_to_py(py, item.value, item.id, item.index)
+            }
+        })
+        .collect()
+    }
+
+    // converts a value read from prop of this object, nested objects are returned as proxies
+    // and counters are bound to prop, so they can be incremented
+    fn value_to_py(
+        &self,
+        py: Python<'_>,
+        value: Value<'_>,
+        id: ObjId,
+        prop: impl Into<Prop>,
+    ) -> PyResult<PyObject> {
+        match value {
+            Value::Object(ty) => {
+                Document::for_subfield(py, self.automerge.clone(), ty, id, self.heads.clone())
+            }
+            Value::Scalar(s) => match &*s {
+                ScalarValue::Counter(counter) => BoundCounter::bind(py, self, prop, counter.into()),
+                s => Ok(scalar_to_py(py, s)),
+            },
+        }
+    }
+
+    // the content of this object (at the given heads) as plain python values
+    fn to_py(&self, py: Python<'_>, heads: Option<Vec<HashArg<'_>>>) -> PyResult<PyObject> {
+        match heads {
+            Some(heads) => self.view_at(heads)?.materialized(py),
+            None => self.materialized(py),
+        }
+    }
+
+    // the content of this object as plain python values, see materialize
+    fn materialized(&self, py: Python<'_>) -> PyResult<PyObject> {
+        with_doc! {self, |doc| {
+            let ty = doc.object_type(&self.obj_id).map_err(AutomergeError::AutomergeError)?;
+            materialize(py, doc, Value::Object(ty), self.obj_id.clone(), self.heads.as_deref())
+        }}
+    }
+
+    // compares the content with other like the plain python values would,
+    // so types they can't be compared with give NotImplemented
+    fn content_eq(&self, py: Python<'_>, other: &PyAny) -> PyResult<PyObject> {
+        self.materialized(py)?
+            .call_method1(py, "__eq__", (comparable(py, other)?,))
+    }
+
+    // hashes the identity of the object (the document it belongs to and obj_id), not its content.
+    // Proxies with equal content but different objects (e.g. in a fork) compare equal but hash differently,
+    // so dicts and sets keyed by proxies find the same object, not equal objects
+    fn identity_hash(&self, py: Python<'_>) -> PyResult<isize> {
+        (
+            Arc::as_ptr(&self.automerge) as usize,
+            self.obj_id.to_string(),
+        )
+            .to_object(py)
+            .as_ref(py)
+            .hash()
+    }
+
+    // returns a read only view of this object at the given heads
+    fn view_at(&self, heads: Vec<HashArg<'_>>) -> PyResult<Document> {
+        let heads = extract_heads(heads)?;
+        with_doc! {self, |doc| {
+            check_heads(doc, &heads)?
+        }};
+        Ok(Document {
+            heads: Some(heads),
+            ..self.clone()
+        })
+    }
+
+    // reads the content of the text at a key or index as it was at the given heads
+    fn text_at(&self, prop: Prop, heads: Vec<HashArg<'_>>) -> PyResult<String> {
+        let view = self.view_at(heads)?;
+        let heads = view.heads.as_deref().unwrap();
+        with_doc! {view, |doc| {
+            match get(doc, view.obj_id.clone(), prop.clone(), Some(heads))? {
+                Some((Value::Object(ObjType::Text), id)) => {
+                    Ok(doc.text_at(id, heads).map_err(AutomergeError::AutomergeError)?)
+                }
+                Some(_) => Err(PyTypeError::new_err(format!("{prop} is not a text"))),
+                None => match prop {
+                    Prop::Map(key) => Err(PyKeyError::new_err(key)),
+                    Prop::Seq(index) => Err(PyIndexError::new_err(format!("index {index} out of range"))),
+                },
+            }
+        }}
+    }
+
+    // reads all conflicting values of a key or index together with the id of the operation which set them
+    fn get_conflicts(&self, py: Python<'_>, prop: Prop) -> PyResult<Vec<(PyObject, String)>> {
+        with_doc! {self, |doc| {
+            get_all(doc, self.obj_id.clone(), prop, self.heads.as_deref())?.into_iter().map(|(value, id)| {
+                let opid = id.to_string();
+                let value = match value {
+                    Value::Object(ty) => Document::for_subfield(py, self.automerge.clone(), ty, id, self.heads.clone())?,
+                    Value::Scalar(s) => scalar_to_py(py, &s),
+                };
+                Ok((value, opid))
+            }).collect()
+        }}
+    }
+
+    // describes the operations which wrote the conflicting values of a key or index (in the order of get_conflicts)
+    // together with the change they are part of
+    fn modified_info(&self, py: Python<'_>, prop: Prop) -> PyResult<Vec<ModifiedInfo>> {
+        with_doc! {self, |doc| {
+            let values = get_all(doc, self.obj_id.clone(), prop, self.heads.as_deref())?;
+            PyResult::Ok(values.into_iter().filter_map(|(_, id)| {
+                let change = doc.hash_for_opid(&id).and_then(|hash| doc.get_change_by_hash(&hash));
+                match id {
+                    ObjId::Id(counter, actor, _) => Some(ModifiedInfo::new(py, change, counter, &actor)),
+                    ObjId::Root => None,
+                }
+            }).collect())
+        }}
+    }
+
+    // reads the value at a index of a list, returns None if the index is out of bounds
+    fn get_index(&self, py: Python<'_>, index: usize) -> PyResult<Option<PyObject>> {
+        with_doc! {self, |doc| {
+            if index >= length(doc, self.obj_id.clone(), self.heads.as_deref()) {
+                return Ok(None);
+            }
+            read_value(py, doc, self.obj_id.clone(), index, self.heads.as_deref(), |ty, obj_id| {
+                Document::for_subfield(py, self.automerge.clone(), ty, obj_id, self.heads.clone())
+            }, Some(|value| BoundCounter::bind(py, self, index, value))).map(Some)
+        }}
+    }
+}
+
 #[pymethods]
 impl Document {
     fn __len__(&self) -> PyResult<usize> {
+        self.ensure_live()?;
         with_doc! {self, |doc| {
-            Ok(doc.length(self.obj_id.clone()))
+            Ok(length(doc, self.obj_id.clone(), self.heads.as_deref()))
         }}
     }
     fn dump(&self) -> PyResult<()> {
@@ -122,53 +525,354 @@ impl Document {
             Ok(doc.dump())
         }}
     }
+
+    // the id of the object in the document, "_root" or "<counter>@<actor>" like in patches and changes
+    #[getter]
+    fn obj_id(&self) -> String {
+        self.obj_id.to_string()
+    }
+
+    // the type of the object: "map", "list", "table" or "text"
+    #[getter]
+    fn object_type(&self) -> PyResult<&'static str> {
+        with_doc! {self, |doc| {
+            Ok(obj_type_name(doc.object_type(&self.obj_id).map_err(AutomergeError::AutomergeError)?))
+        }}
+    }
+
+    // the keys and indices leading from the root to this object, e.g. ["items", 3, "meta"]
+    fn path(&self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        self.ensure_live()?;
+        with_doc! {self, |doc| {
+            Ok(obj_path(doc, &self.obj_id, self.heads.as_deref())
+                .into_iter()
+                .map(|prop| prop_to_py(py, prop))
+                .collect())
+        }}
+    }
+
+    // whether the object is still part of the document, using the proxy raises StaleObjectError otherwise
+    fn is_live(&self) -> PyResult<bool> {
+        let state = lock_document(
+            &self.automerge,
+            |automerge| automerge.read(),
+            |state| state.doc.is_some() || state.snapshot.is_some(),
+        )?;
+        let doc = state.doc.as_ref().or(state.snapshot.as_ref()).unwrap();
+        Ok(is_live(doc, &self.obj_id, self.heads.as_deref()))
+    }
+
+    // a shallow copy is another proxy for the same object of the same underlying document
+    fn __copy__(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let ty = with_doc! {self, |doc| {
+            doc.object_type(&self.obj_id).map_err(AutomergeError::AutomergeError)?
+        }};
+        Document::for_subfield(
+            py,
+            self.automerge.clone(),
+            ty,
+            self.obj_id.clone(),
+            self.heads.clone(),
+        )
+    }
+
+    // a deep copy of the root is a fork (with a new actor id) of the document, or of the viewed heads.
+    // Nested objects can't exist outside of their document, so they are copied as plain python values (see to_py)
+    fn __deepcopy__(&self, py: Python<'_>, _memo: &PyAny) -> PyResult<PyObject> {
+        if self.obj_id != automerge::ROOT {
+            return self.materialized(py);
+        }
+        let new_doc = with_doc! {self, |doc| {
+            match &self.heads {
+                Some(heads) => doc.fork_at(heads).map_err(AutomergeError::AutomergeError)?,
+                None => doc.fork(),
+            }
+        }};
+        Document::from_loaded_doc(py, new_doc)
+    }
+}
+
+// reads the length of a object, optionally at the given heads
+fn length<T: ReadDoc>(doc: &T, obj_id: ObjId, heads: Option<&[ChangeHash]>) -> usize {
+    match heads {
+        Some(heads) => doc.length_at(obj_id, heads),
+        None => doc.length(obj_id),
+    }
+}
+
+// a cursor to the element at the (possibly negative) index of a list or text, optionally at the given heads
+fn cursor<T: ReadDoc>(
+    doc: &T,
+    obj_id: &ObjId,
+    index: isize,
+    heads: Option<&[ChangeHash]>,
+) -> PyResult<Cursor> {
+    let kind = obj_type_name(
+        doc.object_type(obj_id)
+            .map_err(AutomergeError::AutomergeError)?,
+    );
+    let index = sequence_index(index, length(doc, obj_id.clone(), heads), kind)?;
+    let cursor = doc
+        .get_cursor(obj_id, index, heads)
+        .map_err(AutomergeError::AutomergeError)?;
+    Ok(Cursor(cursor))
+}
+
+// the index of the element a cursor points to, optionally at the given heads
+// cursors to deleted elements resolve to the index the element would have now,
+// i.e. the index of the next element that is still there (or the length, if there is none)
+fn cursor_position<T: ReadDoc>(
+    doc: &T,
+    obj_id: &ObjId,
+    cursor: &Cursor,
+    heads: Option<&[ChangeHash]>,
+) -> PyResult<usize> {
+    Ok(doc
+        .get_cursor_position(obj_id, &cursor.0, heads)
+        .map_err(AutomergeError::AutomergeError)?)
+}
+
+// reads the keys of a map, optionally at the given heads
+fn keys<T: ReadDoc>(doc: &T, obj_id: ObjId, heads: Option<&[ChangeHash]>) -> Vec<String> {
+    match heads {
+        Some(heads) => doc.keys_at(obj_id, heads).collect(),
+        None => doc.keys(obj_id).collect(),
+    }
+}
+
+// reads all values of a list, optionally at the given heads
+fn list_values<'a, T: ReadDoc>(
+    doc: &'a T,
+    obj_id: ObjId,
+    heads: Option<&[ChangeHash]>,
+) -> Vec<Value<'a>> {
+    match heads {
+        Some(heads) => doc
+            .list_range_at(obj_id, .., heads)
+            .map(|item| item.value)
+            .collect(),
+        None => doc.list_range(obj_id, ..).map(|item| item.value).collect(),
+    }
+}
+
+// reads at most limit keys of a map which are in the given range, optionally at the given heads
+fn keys_in<T: ReadDoc>(
+    doc: &T,
+    obj_id: ObjId,
+    range: (Bound<String>, Bound<String>),
+    limit: usize,
+    heads: Option<&[ChangeHash]>,
+) -> Vec<String> {
+    match heads {
+        Some(heads) => doc
+            .map_range_at(obj_id, range, heads)
+            .take(limit)
+            .map(|item| item.key.to_string())
+            .collect(),
+        None => doc
+            .map_range(obj_id, range)
+            .take(limit)
+            .map(|item| item.key.to_string())
+            .collect(),
+    }
+}
+
+// the number of keys of a map in the given range, optionally at the given heads
+fn count_keys_in<T: ReadDoc>(
+    doc: &T,
+    obj_id: ObjId,
+    range: (Bound<String>, Bound<String>),
+    heads: Option<&[ChangeHash]>,
+) -> usize {
+    match heads {
+        Some(heads) => doc.map_range_at(obj_id, range, heads).count(),
+        None => doc.map_range(obj_id, range).count(),
+    }
+}
+
+// reads a value, optionally at the given heads
+fn get<'a, T: ReadDoc>(
+    doc: &'a T,
+    obj_id: ObjId,
+    prop: impl Into<Prop>,
+    heads: Option<&[ChangeHash]>,
+) -> Result<Option<(Value<'a>, ObjId)>, AutomergeError> {
+    match heads {
+        Some(heads) => doc.get_at(obj_id, prop, heads),
+        None => doc.get(obj_id, prop),
+    }
+    .map_err(AutomergeError::AutomergeError)
+}
+
+// reads all conflicting values, optionally at the given heads
+// list indices out of bounds raise a IndexError
+fn get_all<'a, T: ReadDoc>(
+    doc: &'a T,
+    obj_id: ObjId,
+    prop: Prop,
+    heads: Option<&[ChangeHash]>,
+) -> PyResult<Vec<(Value<'a>, ObjId)>> {
+    if let Prop::Seq(index) = prop {
+        let length = length(doc, obj_id.clone(), heads);
+        if index >= length {
+            return Err(PyIndexError::new_err(format!(
+                "index {index} is greater than length {length}"
+            )));
+        }
+    }
+    Ok(match heads {
+        Some(heads) => doc.get_all_at(obj_id, prop, heads),
+        None => doc.get_all(obj_id, prop),
+    }
+    .map_err(AutomergeError::AutomergeError)?)
 }
 
 // converts a automerge value to the appropriate python value
+// If heads are given, the value is read as it was at these heads
 fn read_value<'a, T: ReadDoc>(
     py: Python<'_>,
     doc: &T,
     obj_id: ObjId,
     name: impl Into<IndexOrName<'a>>,
+    heads: Option<&[ChangeHash]>,
     nested_handler: impl FnOnce(ObjType, ObjId) -> PyResult<PyObject>,
-    counter_handler: Option<impl FnOnce() -> PyResult<PyObject>>,
+    counter_handler: Option<impl FnOnce(i64) -> PyResult<PyObject>>,
 ) -> PyResult<PyObject> {
-    match doc
-        .get(obj_id.clone(), name.into())
-        .map_err(AutomergeError::AutomergeError)?
-    {
+    match get(doc, obj_id, name.into(), heads)? {
         Some((Value::Object(ty), id)) => nested_handler(ty, id),
-        Some((Value::Scalar(s), _)) => {
-            use ScalarValue::*;
-            let s = &*s;
-            Ok(match s {
-                Bytes(b) => b.to_object(py),
-                Str(s) => s.to_object(py),
-                Int(i) => i.to_object(py),
-                Uint(i) => i.to_object(py),
-                F64(f) => f.to_object(py),
-                Counter(c) => {
-                    if let Some(counter_handler) = counter_handler {
-                        counter_handler()?
-                    } else {
-                        crate::Counter(c.into()).into_py(py)
-                    }
-                }
-                // TODO(robin): this probably should become a date?
-                Timestamp(t) => t.to_object(py),
-                Boolean(b) => b.to_object(py),
-                Unknown { type_code, bytes } => crate::Unknown {
-                    type_code: *type_code,
-                    bytes: bytes.to_vec(),
-                }
-                .into_py(py),
-                Null => ().to_object(py),
-            })
-        }
+        Some((Value::Scalar(s), _)) => match (&*s, counter_handler) {
+            (ScalarValue::Counter(counter), Some(counter_handler)) => {
+                counter_handler(counter.into())
+            }
+            (s, _) => Ok(scalar_to_py(py, s)),
+        },
         None => Ok(().to_object(py)),
     }
 }
 
+// converts a automerge scalar value to the appropriate python value
+fn scalar_to_py(py: Python<'_>, s: &ScalarValue) -> PyObject {
+    use ScalarValue::*;
+    match s {
+        Bytes(b) => PyBytes::new(py, b).into_py(py),
+        Str(s) => s.to_object(py),
+        Int(i) => i.to_object(py),
+        Uint(i) => i.to_object(py),
+        F64(f) => f.to_object(py),
+        Counter(c) => crate::Counter(c.into()).into_py(py),
+        // timestamps outside of the range of datetime.datetime are returned as raw milliseconds
+        Timestamp(t) if RAW_TIMESTAMPS.with(Cell::get) => PyTimestamp(*t).into_py(py),
+        Timestamp(t) => {
+            timestamp_to_datetime(py, *t).unwrap_or_else(|_| PyTimestamp(*t).into_py(py))
+        }
+        Boolean(b) => b.to_object(py),
+        Unknown { type_code, bytes } => crate::Unknown {
+            type_code: *type_code,
+            bytes: bytes.to_vec(),
+        }
+        .into_py(py),
+        Null => ().to_object(py),
+    }
+}
+
+// converts a automerge value into plain python values, recursing into nested objects
+// (maps become dicts, lists become lists, text becomes a str and counters become ints)
+fn materialize<T: ReadDoc>(
+    py: Python<'_>,
+    doc: &T,
+    value: Value<'_>,
+    obj_id: ObjId,
+    heads: Option<&[ChangeHash]>,
+) -> PyResult<PyObject> {
+    match value {
+        Value::Object(ObjType::Map | ObjType::Table) => {
+            let dict = PyDict::new(py);
+            let items = match heads {
+                Some(heads) => doc.map_range_at(obj_id, .., heads),
+                None => doc.map_range(obj_id, ..),
+            };
+            for item in items {
+                dict.set_item(item.key, materialize(py, doc, item.value, item.id, heads)?)?;
+            }
+            Ok(dict.into_py(py))
+        }
+        Value::Object(ObjType::List) => match heads {
+            Some(heads) => doc.list_range_at(obj_id, .., heads),
+            None => doc.list_range(obj_id, ..),
+        }
+        .map(|item| materialize(py, doc, item.value, item.id, heads))
+        .collect::<PyResult<Vec<_>>>()
+        .map(|values| values.into_py(py)),
+        Value::Object(ObjType::Text) => Ok(match heads {
+            Some(heads) => doc.text_at(obj_id, heads),
+            None => doc.text(obj_id),
+        }
+        .map_err(AutomergeError::AutomergeError)?
+        .into_py(py)),
+        Value::Scalar(s) => Ok(match &*s {
+            ScalarValue::Counter(counter) => i64::from(counter).into_py(py),
+            s => scalar_to_py(py, s),
+        }),
+    }
+}
+
+// converts document proxies and text into plain python values, so they can be compared with python equality,
+// other values are returned unchanged
+fn comparable(py: Python<'_>, value: &PyAny) -> PyResult<PyObject> {
+    if let Ok(document) = value.extract::<PyRef<'_, Document>>() {
+        document.materialized(py)
+    } else if let Ok(text) = value.extract::<PyRef<'_, Text>>() {
+        Ok(text.text.clone().into_py(py))
+    } else if let Ok(text) = value.extract::<PyRef<'_, TextView>>() {
+        Ok(text.text()?.into_py(py))
+    } else {
+        Ok(value.into_py(py))
+    }
+}
+
+// compares a automerge value with a python value using python equality
+// nested objects are never equal to anything
+fn scalar_eq(py: Python<'_>, value: &Value<'_>, other: &PyAny) -> PyResult<bool> {
+    match value {
+        Value::Scalar(s) => scalar_to_py(py, s).as_ref(py).eq(other),
+        Value::Object(_) => Ok(false),
+    }
+}
+
+// finds the first value equal to the given python value in values[start..stop],
+// with start and stop interpreted like the arguments of list.index
+fn position(
+    py: Python<'_>,
+    values: &[Value<'_>],
+    value: &PyAny,
+    start: isize,
+    stop: Option<isize>,
+) -> PyResult<Option<usize>> {
+    let length = values.len() as isize;
+    let clamp = |index: isize| {
+        let index = if index < 0 { index + length } else { index };
+        index.clamp(0, length) as usize
+    };
+    let (start, stop) = (clamp(start), clamp(stop.unwrap_or(length)));
+    for (index, item) in values.iter().enumerate().take(stop).skip(start) {
+        if scalar_eq(py, item, value)? {
+            return Ok(Some(index));
+        }
+    }
+    Ok(None)
+}
+
+// counts the values equal to the given python value
+fn count(py: Python<'_>, values: &[Value<'_>], value: &PyAny) -> PyResult<usize> {
+    let mut count = 0;
+    for item in values {
+        if scalar_eq(py, item, value)? {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
 #[derive(FromPyObject)]
 enum IndexOrName<'a> {
     Int(usize),
@@ -213,27 +917,358 @@ pub struct Sequence;
 #[pymethods]
 impl Mapping {
     fn __getitem__(slf: PyRef<'_, Self>, py: Python<'_>, name: &'_ str) -> PyResult<PyObject> {
-        Mapping::__getattr__(slf, py, name)
+        slf.as_ref().ensure_live()?;
+        slf.as_ref()
+            .get_key(py, name)?
+            .ok_or_else(|| PyKeyError::new_err(name.to_string()))
     }
 
     fn __getattr__(slf: PyRef<'_, Self>, py: Python<'_>, name: &'_ str) -> PyResult<PyObject> {
+        // python looks up all kinds of special methods on objects (copy, pickle, ...),
+        // these should never be resolved from the document
+        if is_dunder(name) {
+            return Err(PyAttributeError::new_err(name.to_string()));
+        }
         let super_ = slf.as_ref();
-        with_doc! {super_, |doc| {
-            read_value(py, doc, super_.obj_id.clone(), name, |ty, obj_id| {
-                Document::for_subfield(py, doc, super_.automerge.clone(), ty, obj_id)
-            }, Option::<fn() -> _>::None)
+        super_.ensure_live()?;
+        super_
+            .get_key(py, name)?
+            .ok_or_else(|| PyAttributeError::new_err(format!("{name}{}", super_.location())))
+    }
+
+    #[pyo3(signature = (name, default=None))]
+    fn get(
+        slf: PyRef<'_, Self>,
+        py: Python<'_>,
+        name: &'_ str,
+        default: Option<PyObject>,
+    ) -> PyResult<PyObject> {
+        Ok(slf
+            .as_ref()
+            .get_key(py, name)?
+            .unwrap_or_else(|| default.unwrap_or_else(|| py.None())))
+    }
+
+    fn text_at(slf: PyRef<'_, Self>, name: &'_ str, heads: Vec<HashArg<'_>>) -> PyResult<String> {
+        slf.as_ref().text_at(name.into(), heads)
+    }
+
+    fn keys_at(slf: PyRef<'_, Self>, heads: Vec<HashArg<'_>>) -> PyResult<Vec<String>> {
+        let view = slf.as_ref().view_at(heads)?;
+        with_doc! {view, |doc| {
+            Ok(keys(doc, view.obj_id.clone(), view.heads.as_deref()))
         }}
     }
-}
 
-// TODO(robin): consider implementing the sequence iterator on our own?
-// Maybe thats faster...
-#[pymethods]
-impl Sequence {
-    fn __getitem__(slf: PyRef<'_, Self>, py: Python<'_>, mut index: isize) -> PyResult<PyObject> {
+    fn length_at(slf: PyRef<'_, Self>, heads: Vec<HashArg<'_>>) -> PyResult<usize> {
+        slf.as_ref().view_at(heads)?.__len__()
+    }
+
+    // reads the value of a key as it was at the given heads
+    fn get_at(
+        slf: PyRef<'_, Self>,
+        py: Python<'_>,
+        name: &'_ str,
+        heads: Vec<HashArg<'_>>,
+    ) -> PyResult<PyObject> {
+        slf.as_ref()
+            .view_at(heads)?
+            .get_key(py, name)?
+            .ok_or_else(|| PyKeyError::new_err(name.to_string()))
+    }
+
+    fn get_all(
+        slf: PyRef<'_, Self>,
+        py: Python<'_>,
+        name: &'_ str,
+    ) -> PyResult<Vec<(PyObject, String)>> {
+        slf.as_ref().get_conflicts(py, name.into())
+    }
+
+    // who wrote the current value of the key and when, one entry for each conflicting value
+    fn modified_info(
+        slf: PyRef<'_, Self>,
+        py: Python<'_>,
+        name: &'_ str,
+    ) -> PyResult<Vec<ModifiedInfo>> {
+        slf.as_ref().modified_info(py, name.into())
+    }
+
+    // converts the whole subtree into plain python values under a single lock,
+    // see materialize for how values are converted
+    #[pyo3(signature = (heads=None))]
+    fn to_py(
+        slf: PyRef<'_, Self>,
+        py: Python<'_>,
+        heads: Option<Vec<HashArg<'_>>>,
+    ) -> PyResult<PyObject> {
+        slf.as_ref().to_py(py, heads)
+    }
+
+    // maps are equal to maps and dicts with the same keys and equal values,
+    // conflicts are compared using the winning value
+    fn __eq__(slf: PyRef<'_, Self>, py: Python<'_>, other: &PyAny) -> PyResult<PyObject> {
+        slf.as_ref().content_eq(py, other)
+    }
+
+    // see Document::identity_hash
+    fn __hash__(slf: PyRef<'_, Self>, py: Python<'_>) -> PyResult<isize> {
+        slf.as_ref().identity_hash(py)
+    }
+
+    fn __contains__(slf: PyRef<'_, Self>, name: &'_ str) -> PyResult<bool> {
+        let super_ = slf.as_ref();
+        with_doc! {super_, |doc| {
+            Ok(get(doc, super_.obj_id.clone(), name, super_.heads.as_deref())?.is_some())
+        }}
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyResult<KeysIterator> {
+        slf.as_ref().ensure_live()?;
+        Ok(KeysIterator {
+            document: slf.as_ref().clone(),
+            keys: KeyChunks::new(None, None),
+        })
+    }
+
+    // iterates over the (key, value) pairs with keys from start_key (inclusive) to end_key (exclusive),
+    // only the keys in this range are read
+    #[pyo3(signature = (start_key=None, end_key=None))]
+    fn range(
+        slf: PyRef<'_, Self>,
+        start_key: Option<String>,
+        end_key: Option<String>,
+    ) -> EntriesIterator {
+        EntriesIterator {
+            document: slf.as_ref().clone(),
+            keys: EntryKeys::Map(KeyChunks::new(start_key, end_key)),
+        }
+    }
+
+    fn keys(slf: PyRef<'_, Self>) -> PyResult<Vec<String>> {
+        let super_ = slf.as_ref();
+        with_doc! {super_, |doc| {
+            Ok(keys(doc, super_.obj_id.clone(), super_.heads.as_deref()))
+        }}
+    }
+
+    fn values(slf: PyRef<'_, Self>, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        Ok(Mapping::items(slf, py)?
+            .into_iter()
+            .map(|(_, value)| value)
+            .collect())
+    }
+
+    // reads all entries in a single pass over the map
+    fn items(slf: PyRef<'_, Self>, py: Python<'_>) -> PyResult<Vec<(String, PyObject)>> {
+        let super_ = slf.as_ref();
+        with_doc! {super_, |doc| {
+            match super_.heads.as_deref() {
+                Some(heads) => doc.map_range_at(&super_.obj_id, .., heads),
+                None => doc.map_range(&super_.obj_id, ..),
+            }
+            .map(|item| Ok((item.key.to_string(), super_.value_to_py(py, item.value, item.id, item.key)?)))
+            .collect()
+        }}
+    }
+}
+
+#[pymethods]
+impl Sequence {
+    // see Mapping.to_py
+    #[pyo3(signature = (heads=None))]
+    fn to_py(
+        slf: PyRef<'_, Self>,
+        py: Python<'_>,
+        heads: Option<Vec<HashArg<'_>>>,
+    ) -> PyResult<PyObject> {
+        slf.as_ref().to_py(py, heads)
+    }
+
+    // lists are equal to lists with equal elements
+    fn __eq__(slf: PyRef<'_, Self>, py: Python<'_>, other: &PyAny) -> PyResult<PyObject> {
+        slf.as_ref().content_eq(py, other)
+    }
+
+    // see Document::identity_hash
+    fn __hash__(slf: PyRef<'_, Self>, py: Python<'_>) -> PyResult<isize> {
+        slf.as_ref().identity_hash(py)
+    }
+
+    fn __contains__(slf: PyRef<'_, Self>, py: Python<'_>, value: &PyAny) -> PyResult<bool> {
+        let super_ = slf.as_ref();
+        with_doc! {super_, |doc| {
+            for item in list_values(doc, super_.obj_id.clone(), super_.heads.as_deref()) {
+                if scalar_eq(py, &item, value)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }}
+    }
+
+    fn text_at(slf: PyRef<'_, Self>, index: usize, heads: Vec<HashArg<'_>>) -> PyResult<String> {
+        slf.as_ref().text_at(index.into(), heads)
+    }
+
+    fn length_at(slf: PyRef<'_, Self>, heads: Vec<HashArg<'_>>) -> PyResult<usize> {
+        slf.as_ref().view_at(heads)?.__len__()
+    }
+
+    // iterates over the (index, value) pairs from start to stop (with the semantics of a slice)
+    #[pyo3(signature = (start=None, stop=None))]
+    fn range(
+        slf: PyRef<'_, Self>,
+        py: Python<'_>,
+        start: Option<isize>,
+        stop: Option<isize>,
+    ) -> PyResult<EntriesIterator> {
+        let super_ = slf.as_ref();
+        let length = super_.__len__()?;
+        let indices = PySlice::new(py, start.unwrap_or(0), stop.unwrap_or(isize::MAX), 1)
+            .indices(length as _)?;
+        let start = indices.start as usize;
+        Ok(EntriesIterator {
+            document: super_.clone(),
+            keys: EntryKeys::Sequence {
+                index: start,
+                stop: start + indices.slicelength as usize,
+            },
+        })
+    }
+
+    // the elements from start to stop (with the semantics of a slice) as a list, read under a single lock,
+    // nested objects are returned as proxies, or as plain python values if deep is set
+    #[pyo3(signature = (start=None, stop=None, deep=false))]
+    fn to_list(
+        slf: PyRef<'_, Self>,
+        py: Python<'_>,
+        start: Option<isize>,
+        stop: Option<isize>,
+        deep: bool,
+    ) -> PyResult<Vec<PyObject>> {
+        let super_ = slf.as_ref();
+        with_doc! {super_, |doc| {
+            let length = length(doc, super_.obj_id.clone(), super_.heads.as_deref());
+            let indices = PySlice::new(py, start.unwrap_or(0), stop.unwrap_or(isize::MAX), 1).indices(length as _)?;
+            let start = indices.start as usize;
+            super_.read_range(py, doc, start, start + indices.slicelength as usize, deep)
+        }}
+    }
+
+    // a stable reference to the element at index, which keeps pointing to the same
+    // element when elements are inserted or deleted before it (see cursor_position)
+    fn cursor(slf: PyRef<'_, Self>, index: isize) -> PyResult<Cursor> {
+        let super_ = slf.as_ref();
+        with_doc!(super_, |doc| {
+            cursor(doc, &super_.obj_id, index, super_.heads.as_deref())
+        })
+    }
+
+    // the current index of the element the cursor points to, in the state of the list at heads if given
+    // if the element was deleted, this is the index of the first element after it that is still there
+    #[pyo3(signature = (cursor, heads=None))]
+    fn cursor_position(
+        slf: PyRef<'_, Self>,
+        cursor: &Cursor,
+        heads: Option<Vec<HashArg<'_>>>,
+    ) -> PyResult<usize> {
+        let heads = heads.map(extract_heads).transpose()?;
+        let super_ = slf.as_ref();
+        with_doc!(super_, |doc| {
+            let heads = heads.as_deref().or(super_.heads.as_deref());
+            cursor_position(doc, &super_.obj_id, cursor, heads)
+        })
+    }
+
+    // reads the value at a index as it was at the given heads
+    fn get_at(
+        slf: PyRef<'_, Self>,
+        py: Python<'_>,
+        index: usize,
+        heads: Vec<HashArg<'_>>,
+    ) -> PyResult<PyObject> {
+        slf.as_ref()
+            .view_at(heads)?
+            .get_index(py, index)?
+            .ok_or_else(|| PyIndexError::new_err(format!("index {index} out of range")))
+    }
+
+    fn get_all(
+        slf: PyRef<'_, Self>,
+        py: Python<'_>,
+        index: usize,
+    ) -> PyResult<Vec<(PyObject, String)>> {
+        slf.as_ref().get_conflicts(py, index.into())
+    }
+
+    // who wrote the current value at the index and when, one entry for each conflicting value
+    fn modified_info(
+        slf: PyRef<'_, Self>,
+        py: Python<'_>,
+        index: usize,
+    ) -> PyResult<Vec<ModifiedInfo>> {
+        slf.as_ref().modified_info(py, index.into())
+    }
+
+    #[pyo3(signature = (value, start=0, stop=None))]
+    fn index(
+        slf: PyRef<'_, Self>,
+        py: Python<'_>,
+        value: &PyAny,
+        start: isize,
+        stop: Option<isize>,
+    ) -> PyResult<usize> {
+        let super_ = slf.as_ref();
+        with_doc! {super_, |doc| {
+            let values = list_values(doc, super_.obj_id.clone(), super_.heads.as_deref());
+            position(py, &values, value, start, stop)?.ok_or_else(|| PyValueError::new_err("x not in list"))
+        }}
+    }
+
+    fn count(slf: PyRef<'_, Self>, py: Python<'_>, value: &PyAny) -> PyResult<usize> {
+        let super_ = slf.as_ref();
+        with_doc! {super_, |doc| {
+            count(py, &list_values(doc, super_.obj_id.clone(), super_.heads.as_deref()), value)
+        }}
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyResult<SequenceIterator> {
+        slf.as_ref().ensure_live()?;
+        Ok(SequenceIterator {
+            document: slf.as_ref().clone(),
+            index: 0,
+        })
+    }
+
+    fn __getitem__(
+        slf: PyRef<'_, Self>,
+        py: Python<'_>,
+        index_or_slice: SliceOrIndex<'_>,
+    ) -> PyResult<PyObject> {
         let super_ = slf.as_ref();
+        super_.ensure_live()?;
         with_doc! {super_, |doc| {
-            let length = doc.length(super_.obj_id.clone());
+            let length = length(doc, super_.obj_id.clone(), super_.heads.as_deref());
+            let mut index = match index_or_slice {
+                SliceOrIndex::Index(index) => index,
+                SliceOrIndex::Slice(slice) => {
+                    let slice = slice.indices(length as _)?;
+                    if slice.step == 1 {
+                        let start = slice.start as usize;
+                        let values = super_.read_range(py, doc, start, start + slice.slicelength as usize, false)?;
+                        return Ok(values.into_py(py));
+                    }
+                    let mut values = Vec::with_capacity(slice.slicelength as usize);
+                    for i in 0..slice.slicelength {
+                        let index = (slice.start + i * slice.step) as usize;
+                        values.push(read_value(py, doc, super_.obj_id.clone(), index, super_.heads.as_deref(), |ty, obj_id| {
+                            Ok(Document::for_subfield(py, super_.automerge.clone(), ty, obj_id, super_.heads.clone())?.into_py(py))
+                        }, Some(|value| BoundCounter::bind(py, super_, index, value)))?);
+                    }
+                    return Ok(values.into_py(py));
+                }
+            };
             if index < 0 {
                 let isize_length: isize = length.try_into().unwrap();
                 index = index + isize_length;
@@ -243,27 +1278,122 @@ impl Sequence {
             }
             let index: usize = index.try_into().unwrap();
             if index < length {
-                read_value(py, doc, super_.obj_id.clone(), index, |ty, obj_id| {
-                    Ok(Document::for_subfield(py, doc, super_.automerge.clone(), ty, obj_id)?.into_py(py))
-                }, Option::<fn() -> _>::None)
+                read_value(py, doc, super_.obj_id.clone(), index, super_.heads.as_deref(), |ty, obj_id| {
+                    Ok(Document::for_subfield(py, super_.automerge.clone(), ty, obj_id, super_.heads.clone())?.into_py(py))
+                }, Some(|value| BoundCounter::bind(py, super_, index, value)))
             } else {
-                Err(PyIndexError::new_err(format!("index {index} is greater than length {length}")))
+                Err(PyIndexError::new_err(format!(
+                    "index {index} is greater than length {length}{}",
+                    location(doc, &super_.obj_id, super_.heads.as_deref())
+                )))
             }
         }}
     }
 }
 
+// Iterates over the values of a list
+// If the list shrinks during iteration, the iteration simply stops
+#[pyclass]
+pub struct SequenceIterator {
+    document: Document,
+    index: usize,
+}
+
+#[pymethods]
+impl SequenceIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let value = slf.document.get_index(py, slf.index)?;
+        slf.index += 1;
+        Ok(value)
+    }
+}
+
 // fn __setitem__(&self) {
 // }
 
 // fn __delitem__(&self) {
 // }
 
+#[pyclass]
+pub struct KeysIterator {
+    document: Document,
+    keys: KeyChunks,
+}
+
+#[pymethods]
+impl KeysIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> PyResult<Option<String>> {
+        let slf = &mut *slf;
+        slf.keys.next(&slf.document)
+    }
+
+    fn __length_hint__(&self) -> PyResult<usize> {
+        self.keys.remaining(&self.document)
+    }
+}
+
+// the keys of a map in a range, read lazily in chunks (resuming after the last key read),
+// so only the keys in the range are ever read
+struct KeyChunks {
+    chunk: std::vec::IntoIter<String>,
+    from: Bound<String>,
+    to: Bound<String>,
+}
+
+const ENTRIES_CHUNK_SIZE: usize = 256;
+
+impl KeyChunks {
+    // the keys from start (inclusive) to end (exclusive), None for no bound
+    fn new(start: Option<String>, end: Option<String>) -> Self {
+        KeyChunks {
+            chunk: Vec::new().into_iter(),
+            from: start.map_or(Bound::Unbounded, Bound::Included),
+            to: end.map_or(Bound::Unbounded, Bound::Excluded),
+        }
+    }
+
+    fn next(&mut self, document: &Document) -> PyResult<Option<String>> {
+        if self.chunk.len() == 0 {
+            let range = (self.from.clone(), self.to.clone());
+            self.chunk = with_doc! {document, |doc| {
+                keys_in(doc, document.obj_id.clone(), range, ENTRIES_CHUNK_SIZE, document.heads.as_deref())
+            }}
+            .into_iter();
+        }
+        let key = self.chunk.next();
+        if let Some(key) = &key {
+            self.from = Bound::Excluded(key.clone());
+        }
+        Ok(key)
+    }
+
+    // the number of keys after the last key read
+    fn remaining(&self, document: &Document) -> PyResult<usize> {
+        let range = (self.from.clone(), self.to.clone());
+        Ok(with_doc! {document, |doc| {
+            count_keys_in(doc, document.obj_id.clone(), range, document.heads.as_deref())
+        }})
+    }
+}
+
 #[pyclass]
 pub struct EntriesIterator {
-    automerge: AutomergeDocument,
-    obj_id: ObjId,
-    keys: std::vec::IntoIter<String>,
+    document: Document,
+    keys: EntryKeys,
+}
+
+// lists are walked by index until stop or the end is reached
+enum EntryKeys {
+    Map(KeyChunks),
+    Sequence { index: usize, stop: usize },
 }
 
 #[pymethods]
@@ -275,59 +1405,205 @@ impl EntriesIterator {
     fn __next__(
         mut slf: PyRefMut<'_, Self>,
         py: Python<'_>,
-    ) -> PyResult<Option<(String, PyObject)>> {
-        let key = slf.keys.next();
-        Ok(match key {
-            Some(key) => {
-                let value = with_doc! {slf, |doc| {
-                    read_value(py, doc, slf.obj_id.clone(), &key, |ty, obj_id| {
-                        Ok(Document::for_subfield(py, doc, slf.automerge.clone(), ty, obj_id)?.into_py(py))
-                    }, Option::<fn() -> _>::None)?
-                }};
-                Some((key, value))
-            }
-            None => None,
-        })
+    ) -> PyResult<Option<(PyObject, PyObject)>> {
+        let slf = &mut *slf;
+        match &mut slf.keys {
+            EntryKeys::Map(keys) => loop {
+                let Some(key) = keys.next(&slf.document)? else {
+                    return Ok(None);
+                };
+                // keys removed since the chunk was read are skipped
+                if let Some(value) = slf.document.get_key(py, &key)? {
+                    return Ok(Some((key.into_py(py), value)));
+                }
+            },
+            EntryKeys::Sequence { index, stop } => {
+                if *index >= *stop {
+                    return Ok(None);
+                }
+                let entry = slf
+                    .document
+                    .get_index(py, *index)?
+                    .map(|value| ((*index).into_py(py), value));
+                *index += 1;
+                Ok(entry)
+            }
+        }
+    }
+
+    fn __length_hint__(&self) -> PyResult<usize> {
+        match &self.keys {
+            EntryKeys::Map(keys) => keys.remaining(&self.document),
+            EntryKeys::Sequence { index, stop } => {
+                let length = self.document.__len__()?;
+                Ok(length.min(*stop).saturating_sub(*index))
+            }
+        }
     }
 }
 
 // We need use a standalone function, because pyo3 does not support returning
 // a subclass from the constructor
 // and manually overriding __new__ does not seem to be supported
-// It has a additional argument to allow passing a "type", which
+// It accepts a "type" instead of the initial value, which
 // is completely faken in the .pyi files
+// If a mapping is given, the document is populated with it in a first change
 #[pyfunction]
-pub fn init(py: Python<'_>, _ignore: Option<&PyAny>) -> PyResult<PyObject> {
-    Document::from_doc(py, Automerge::new())
+#[pyo3(signature = (initial=None, message=None))]
+pub fn init(
+    py: Python<'_>,
+    initial: Option<&PyAny>,
+    message: Option<String>,
+) -> PyResult<PyObject> {
+    let mut doc = Automerge::new();
+    if let Some(initial) = initial.filter(|initial| !initial.is_instance_of::<PyType>()) {
+        let initial: &PyMapping = initial.downcast()?;
+        let mut tx = doc.transaction();
+        for item in initial.items()?.iter()? {
+            let (key, value): (&str, AutomergeValue) = item?.extract()?;
+            apply_value(&mut tx, None, automerge::ROOT, key, value)?;
+        }
+        match message {
+            Some(message) => tx.commit_with(CommitOptions::default().with_message(message)),
+            None => tx.commit(),
+        };
+    }
+    Document::from_doc(py, doc)
 }
 
-// TODO(robin): check for Sequence. Currently returns empty iterator for sequence
-// TODO(robin): is there a way to not read all the keys at once?
+// If heads are given, the entries are read as they were at these heads
 #[pyfunction]
-pub fn entries(document: &mut Document) -> PyResult<EntriesIterator> {
+#[pyo3(signature = (document, heads=None))]
+pub fn entries(document: &Document, heads: Option<Vec<HashArg<'_>>>) -> PyResult<EntriesIterator> {
+    let document = match heads {
+        Some(heads) => document.view_at(heads)?,
+        None => document.clone(),
+    };
     let keys = with_doc! {document, |doc| {
-        doc.keys(document.obj_id.clone()).collect::<Vec<_>>()
+        match doc.object_type(document.obj_id.clone()).map_err(AutomergeError::AutomergeError)? {
+            ObjType::Map | ObjType::Table => EntryKeys::Map(KeyChunks::new(None, None)),
+            ObjType::List | ObjType::Text => EntryKeys::Sequence { index: 0, stop: usize::MAX },
+        }
     }};
-    Ok(EntriesIterator {
-        keys: keys.into_iter(),
-        obj_id: document.obj_id.clone(),
-        automerge: document.automerge.clone(),
-    })
+    Ok(EntriesIterator { document, keys })
 }
 
+// While the transaction is open, reading the document raises an error (or waits, see set_read_behavior).
+// With snapshot=True the document can still be read (also from other threads) and shows the state from
+// before the transaction. This needs a copy of the whole document for every transaction, so it is opt-in.
+// The time recorded in the change can be given as a datetime or as milliseconds since the unix epoch, it is 0 otherwise.
+// With actor the change is made by the given actor, the actor of the document is restored once the transaction ends.
 #[pyfunction]
+#[pyo3(signature = (doc, message=None, time=None, actor=None, snapshot=false))]
 pub fn transaction(
     py: Python<'_>,
     doc: &mut Document,
     message: Option<String>,
+    time: Option<CommitTime>,
+    actor: Option<Actor<'_>>,
+    snapshot: bool,
 ) -> PyResult<PyObject> {
-    let automerge = doc
-        .automerge
-        .lock()
-        .unwrap()
-        .take()
-        .ok_or(AutomergeError::NestedTransaction)?;
-    DocumentTransaction::new(py, automerge, doc, message)
+    if doc.heads.is_some() {
+        Err(AutomergeError::ModifyingHistoricalView)?
+    }
+    let actor = actor.map(ActorId::try_from).transpose()?;
+    // with the "wait" read behavior, a transaction opened by another thread is waited for
+    let mut state = lock_document(
+        &doc.automerge,
+        |automerge| automerge.write(),
+        |state| {
+            state.doc.is_some()
+                || matches!(state.read_behavior, ReadBehavior::Fail)
+                || state.owned_by_current_thread()
+        },
+    )?;
+    let Some(mut automerge) = state.doc.take() else {
+        Err(AutomergeError::NestedTransaction(
+            state.open_transaction.clone().unwrap_or_default(),
+        ))?
+    };
+    if snapshot {
+        state.snapshot = Some(automerge.clone());
+    }
+    state.open_transaction = Some(describe_transaction(py, message.as_deref()));
+    state.transaction_thread = Some(thread::current().id());
+    drop(state);
+    let restore_actor = actor.map(|actor| {
+        let previous = automerge.get_actor().clone();
+        automerge.set_actor(actor);
+        previous
+    });
+    DocumentTransaction::new(
+        py,
+        automerge,
+        doc,
+        message,
+        time.map(|time| time.0),
+        restore_actor,
+    )
+}
+
+// Calls func with a new transaction, commits it if func returns and rolls it back if func raises.
+// Returns the result of func and the hash of the new change (None if nothing was changed)
+#[pyfunction]
+#[pyo3(signature = (doc, func, message=None, time=None, actor=None, snapshot=false))]
+pub fn transact(
+    py: Python<'_>,
+    doc: &PyCell<Document>,
+    func: &PyAny,
+    message: Option<String>,
+    time: Option<CommitTime>,
+    actor: Option<Actor<'_>>,
+    snapshot: bool,
+) -> PyResult<(PyObject, Option<PyChangeHash>)> {
+    // the document must not stay borrowed while func runs, func might read it
+    let tx = transaction(
+        py,
+        &mut *doc.try_borrow_mut()?,
+        message,
+        time,
+        actor,
+        snapshot,
+    )?;
+    // a handle sharing the transaction, borrowing tx itself would block func from using it
+    let handle = tx.extract::<DocumentTransaction>(py)?;
+    match func.call1((tx.clone_ref(py),)) {
+        Ok(result) => {
+            // func might already have commited itself
+            let holder = handle.transaction.lock().unwrap();
+            if holder.transaction.is_none() {
+                let hash = holder.change_hash;
+                return Ok((result.into_py(py), hash.map(PyChangeHash)));
+            }
+            drop(holder);
+            let hash = handle.finish(None, None, true)?;
+            Ok((result.into_py(py), hash))
+        }
+        Err(err) => {
+            // the transaction is already gone if func rolled it back itself
+            if handle.transaction.lock().unwrap().transaction.is_some() {
+                handle.finish(None, None, false)?;
+            }
+            Err(err)
+        }
+    }
+}
+
+// describes where a transaction is opened, using the python frame calling transaction()
+fn describe_transaction(py: Python<'_>, message: Option<&str>) -> String {
+    let location = py
+        .import("sys")
+        .and_then(|sys| sys.call_method1("_getframe", (0,)))
+        .and_then(|frame| {
+            let filename: String = frame.getattr("f_code")?.getattr("co_filename")?.extract()?;
+            let line: usize = frame.getattr("f_lineno")?.extract()?;
+            Ok(format!("opened at {filename}:{line}"))
+        })
+        .unwrap_or_else(|_| "opened".to_owned());
+    match message {
+        Some(message) => format!("{location} with message {message:?}"),
+        None => location,
+    }
 }
 
 // TODO(robin): Support observers. Currently we don't support observers
@@ -344,17 +1620,46 @@ struct TransactionOwningDocument {
     #[covariant]
     transaction: Option<Tx<'this>>,
 }
-type TransactionHolder = Option<TransactionOwningDocument>;
+
+type TransactionLock = Arc<Mutex<TransactionHolder>>;
+
+// locks a transaction, if another thread holds it (e.g. while running python code which released
+// the GIL) this waits without holding the GIL
+fn lock_transaction<'a>(
+    py: Python<'_>,
+    transaction: &'a TransactionLock,
+) -> PyResult<MutexGuard<'a, TransactionHolder>> {
+    loop {
+        match transaction.try_lock() {
+            Ok(holder) => return Ok(holder),
+            Err(TryLockError::Poisoned(_)) => Err(AutomergeError::PoisonedDocument)?,
+            Err(TryLockError::WouldBlock) => py.allow_threads(|| drop(transaction.lock())),
+        }
+    }
+}
+
+// The open transaction, None once it was commited or rolled back
+#[derive(Debug)]
+struct TransactionHolder {
+    transaction: Option<TransactionOwningDocument>,
+    // set when commit() or rollback() were called explicitly, leaving the with block afterwards does nothing
+    closed: bool,
+    // the hash of the commited change, None if the transaction was empty or not commited (yet)
+    change_hash: Option<ChangeHash>,
+    // the time passed to transaction(), used when commit() is not given one
+    commit_time: Option<i64>,
+    // the actor of the document if transaction() was given a different one
+    restore_actor: Option<ActorId>,
+}
 
 // Python class providing bindigs to transactions. This again works similar to Document and can refer to any of the Maps or Lists inside the Automerge Document
 #[pyclass(subclass)]
 #[derive(Clone, Debug)]
 pub struct DocumentTransaction {
     automerge: AutomergeDocument,
-    transaction: Arc<Mutex<TransactionHolder>>,
+    transaction: TransactionLock,
     obj_id: ObjId,
     commit_message: Option<String>,
-    change_hash: Option<ChangeHash>,
 }
 impl DocumentTransaction {
     fn new(
@@ -362,6 +1667,8 @@ impl DocumentTransaction {
         automerge: Automerge,
         document: &Document,
         commit_message: Option<String>,
+        commit_time: Option<i64>,
+        restore_actor: Option<ActorId>,
     ) -> PyResult<PyObject> {
         let ty = automerge
             .object_type(document.obj_id.clone())
@@ -369,13 +1676,19 @@ impl DocumentTransaction {
         DocumentTransaction::for_subfield(
             py,
             document.automerge.clone(),
-            Arc::new(Mutex::new(Some(
-                TransactionOwningDocumentBuilder {
-                    owner: automerge,
-                    transaction_builder: |owner| Some(owner.transaction()),
-                }
-                .build(),
-            ))),
+            Arc::new(Mutex::new(TransactionHolder {
+                transaction: Some(
+                    TransactionOwningDocumentBuilder {
+                        owner: automerge,
+                        transaction_builder: |owner| Some(owner.transaction()),
+                    }
+                    .build(),
+                ),
+                closed: false,
+                change_hash: None,
+                commit_time,
+                restore_actor,
+            })),
             ty,
             document.obj_id.clone(),
             commit_message,
@@ -385,7 +1698,7 @@ impl DocumentTransaction {
     fn for_subfield(
         py: Python<'_>,
         automerge: AutomergeDocument,
-        transaction: Arc<Mutex<TransactionHolder>>,
+        transaction: TransactionLock,
         ty: ObjType,
         obj_id: ObjId,
         commit_message: Option<String>,
@@ -395,7 +1708,6 @@ impl DocumentTransaction {
             transaction,
             obj_id,
             commit_message,
-            change_hash: None,
         };
         match ty {
             ObjType::Map | ObjType::Table => {
@@ -420,67 +1732,280 @@ impl DocumentTransaction {
 
 macro_rules! with_transaction {
     ($self:ident, |$tx:ident| $func:tt) => {
-        let mut tx = $self.transaction.lock().unwrap();
-        let tx = tx.as_mut().ok_or(AutomergeError::ReusedTransaction)?;
-        Ok(tx.with_transaction_mut(|tx| {
+        let mut tx = $self
+            .transaction
+            .lock()
+            .map_err(|_| AutomergeError::PoisonedDocument)?;
+        let tx = tx
+            .transaction
+            .as_mut()
+            .ok_or(AutomergeError::ReusedTransaction)?;
+        tx.with_transaction_mut(|tx| {
             let $tx = tx.as_mut().unwrap();
             Result::<_, PyErr>::Ok($func?)
-        })?)
+        })
     };
 }
 
-#[pymethods]
 impl DocumentTransaction {
-    // TODO(robin): maybe split out these?
-    fn __enter__(slf: PyRef<'_, DocumentTransaction>) -> PyResult<PyRef<'_, DocumentTransaction>> {
-        if slf.transaction.lock().unwrap().is_none() {
-            Err(AutomergeError::ReusedTransaction)?
+    // reads the object (with all objects nested in it) to write it somewhere else, this must not be
+    // the transaction being written to (see ProxyValue)
+    fn copy(&self) -> PyResult<CopiedValue> {
+        let holder = Python::with_gil(|py| lock_transaction(py, &self.transaction))?;
+        let tx = holder
+            .transaction
+            .as_ref()
+            .ok_or(AutomergeError::ReusedTransaction)?;
+        Ok(tx.with_transaction(|tx| copy_live_object(tx.as_ref().unwrap(), &self.obj_id, None))?)
+    }
+
+    // raises StaleObjectError if the object was deleted, see Document::ensure_live
+    fn ensure_live(&self) -> PyResult<()> {
+        if self.is_live()? {
+            Ok(())
         } else {
-            Ok(slf)
+            Err(AutomergeError::StaleObject.into())
         }
     }
 
-    fn __exit__(
-        &mut self,
-        ty: Option<&PyAny>,
-        _value: Option<&PyAny>,
-        _traceback: Option<&PyAny>,
-    ) -> PyResult<()> {
-        let mut tx = self
+    // describes where this object is for error messages (see location)
+    fn location(&self) -> String {
+        let location = || -> PyResult<String> {
+            with_transaction! {self, |tx| {
+                PyResult::Ok(location(tx, &self.obj_id, None))
+            }}
+        };
+        location().unwrap_or_default()
+    }
+
+    // commits or rolls back the transaction and hands the document back
+    fn finish(
+        &self,
+        message: Option<String>,
+        time: Option<i64>,
+        commit: bool,
+    ) -> PyResult<Option<PyChangeHash>> {
+        let mut holder = self.transaction.lock().unwrap();
+        let mut tx = holder
             .transaction
-            .lock()
-            .unwrap()
             .take()
             .ok_or(AutomergeError::ReusedTransaction)?;
-        if ty.is_none() {
-            tx.with_transaction_mut(|tx| {
-                let tx = tx.take().unwrap();
-                if let Some(msg) = &self.commit_message {
-                    (self.change_hash, ..) =
-                        tx.commit_with(CommitOptions::default().with_message(msg));
-                    tracing::trace!(?self.change_hash, "commiting tx");
-                } else {
-                    (self.change_hash, ..) = tx.commit();
-                    tracing::trace!(?self.change_hash, "commiting tx");
+        tx.with_transaction_mut(|tx| {
+            let tx = tx.take().unwrap();
+            if commit {
+                let mut options = CommitOptions::default();
+                if let Some(msg) = message.as_ref().or(self.commit_message.as_ref()) {
+                    options.set_message(msg.clone());
                 }
-            });
+                if let Some(time) = time.or(holder.commit_time) {
+                    options.set_time(time);
+                }
+                (holder.change_hash, ..) = tx.commit_with(options);
+                tracing::trace!(?holder.change_hash, "commiting tx");
+            } else {
+                tx.rollback();
+            }
+        });
+        let mut heads = tx.into_heads();
+        if let Some(actor) = holder.restore_actor.take() {
+            heads.owner.set_actor(actor);
         }
+        let mut state = lock_document(&self.automerge, |automerge| automerge.write(), |_| true)?;
+        state.doc = Some(heads.owner);
+        state.snapshot = None;
+        state.open_transaction = None;
+        state.transaction_thread = None;
+        let (count, condvar) = &*state.transactions_finished;
+        *count.lock().unwrap() += 1;
+        condvar.notify_all();
+        Ok(holder.change_hash.map(PyChangeHash))
+    }
 
-        // not commiting automatically rolls back
-        let heads = tx.into_heads();
-        *self.automerge.lock().unwrap() = Some(heads.owner);
-        Ok(())
+    // reads the value of a key of a map, returns None if the key does not exist
+    fn get_key(&self, py: Python<'_>, name: &str) -> PyResult<Option<PyObject>> {
+        with_transaction! {self, |tx| {
+            if get(tx, self.obj_id.clone(), name, None)?.is_none() {
+                return Ok(None);
+            }
+            read_value(py, tx, self.obj_id.clone(), name, None, |ty, obj_id| {
+                DocumentTransaction::for_subfield(py, self.automerge.clone(), self.transaction.clone(), ty, obj_id, None)
+            },
+            Some(|_| CounterTransaction::new(py, self, name))
+            ).map(Some)
+        }}
     }
 
-    fn __len__(&self) -> PyResult<usize> {
+    // reads all conflicting values of a key or index together with the id of the operation which set them
+    fn get_conflicts(&self, py: Python<'_>, prop: Prop) -> PyResult<Vec<(PyObject, String)>> {
         with_transaction! {self, |tx| {
-            PyResult::Ok(tx.length(self.obj_id.clone()))
+            get_all(tx, self.obj_id.clone(), prop, None)?.into_iter().map(|(value, id)| {
+                let opid = id.to_string();
+                let value = match value {
+                    Value::Object(ty) => DocumentTransaction::for_subfield(py, self.automerge.clone(), self.transaction.clone(), ty, id, None)?,
+                    Value::Scalar(s) => scalar_to_py(py, &s),
+                };
+                Ok((value, opid))
+            }).collect::<PyResult<Vec<_>>>()
         }}
     }
 
-    fn get_change(&self) -> PyResult<Option<Change>> {
-        if let Some(hash) = self.change_hash {
-            with_doc!(self, |doc| {
+    // reads the value at a index of a list, returns None if the index is out of bounds
+    fn get_index(&self, py: Python<'_>, index: usize) -> PyResult<Option<PyObject>> {
+        with_transaction! {self, |tx| {
+            if index >= tx.length(self.obj_id.clone()) {
+                return Ok(None);
+            }
+            read_value(py, tx, self.obj_id.clone(), index, None, |ty, obj_id| {
+                DocumentTransaction::for_subfield(py, self.automerge.clone(), self.transaction.clone(), ty, obj_id, None)
+            },
+            Some(|_| CounterTransaction::new(py, self, index))
+            ).map(Some)
+        }}
+    }
+}
+
+#[pymethods]
+impl DocumentTransaction {
+    // TODO(robin): maybe split out these?
+    fn __enter__(slf: PyRef<'_, DocumentTransaction>) -> PyResult<PyRef<'_, DocumentTransaction>> {
+        if slf.transaction.lock().unwrap().transaction.is_none() {
+            Err(AutomergeError::ReusedTransaction)?
+        } else {
+            Ok(slf)
+        }
+    }
+
+    fn __exit__(
+        &self,
+        ty: Option<&PyAny>,
+        _value: Option<&PyAny>,
+        _traceback: Option<&PyAny>,
+    ) -> PyResult<()> {
+        {
+            let holder = self.transaction.lock().unwrap();
+            if holder.transaction.is_none() && holder.closed {
+                return Ok(());
+            }
+        }
+        // not commiting automatically rolls back
+        self.finish(None, None, ty.is_none()).map(|_| ())
+    }
+
+    // commits the transaction (with the message given to transaction() if none is given here)
+    // and returns the hash of the new change, or None if the transaction made no changes.
+    // The transaction can't be used afterwards
+    #[pyo3(signature = (message=None, time=None))]
+    fn commit(
+        &self,
+        message: Option<String>,
+        time: Option<CommitTime>,
+    ) -> PyResult<Option<PyChangeHash>> {
+        let hash = self.finish(message, time.map(|time| time.0), true)?;
+        self.transaction.lock().unwrap().closed = true;
+        Ok(hash)
+    }
+
+    // discards all changes made in the transaction, the transaction can't be used afterwards
+    fn rollback(&self) -> PyResult<()> {
+        self.finish(None, None, false)?;
+        self.transaction.lock().unwrap().closed = true;
+        Ok(())
+    }
+
+    fn __len__(&self) -> PyResult<usize> {
+        self.ensure_live()?;
+        with_transaction! {self, |tx| {
+            PyResult::Ok(tx.length(self.obj_id.clone()))
+        }}
+    }
+
+    // the id of the object in the document, "_root" or "<counter>@<actor>" like in patches and changes
+    #[getter]
+    fn obj_id(&self) -> String {
+        self.obj_id.to_string()
+    }
+
+    // the type of the object: "map", "list", "table" or "text"
+    #[getter]
+    fn object_type(&self) -> PyResult<&'static str> {
+        with_transaction! {self, |tx| {
+            PyResult::Ok(obj_type_name(tx.object_type(&self.obj_id).map_err(AutomergeError::AutomergeError)?))
+        }}
+    }
+
+    // the keys and indices leading from the root to this object, e.g. ["items", 3, "meta"]
+    fn path(&self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        self.ensure_live()?;
+        with_transaction! {self, |tx| {
+            PyResult::Ok(obj_path(tx, &self.obj_id, None)
+                .into_iter()
+                .map(|prop| prop_to_py(py, prop))
+                .collect())
+        }}
+    }
+
+    // whether the object is still part of the document, using the proxy raises StaleObjectError otherwise
+    fn is_live(&self) -> PyResult<bool> {
+        let mut holder = self.transaction.lock().unwrap();
+        let tx = holder
+            .transaction
+            .as_mut()
+            .ok_or(AutomergeError::ReusedTransaction)?;
+        Ok(tx.with_transaction_mut(|tx| is_live(&*tx.as_mut().unwrap(), &self.obj_id, None)))
+    }
+
+    // the number of operations made in the transaction so far
+    fn pending_ops(&self) -> PyResult<usize> {
+        with_transaction! {self, |tx| {
+            PyResult::Ok(tx.pending_ops())
+        }}
+    }
+
+    // the patches describing the changes made in the transaction so far (see pending_patches)
+    fn pending_patches(&self, py: Python<'_>) -> PyResult<Vec<Patch>> {
+        with_transaction! {self, |tx| {
+            let before = tx.get_heads();
+            let mut patches = Vec::new();
+            pending_patches(py, tx, &before, automerge::ROOT, &mut Vec::new(), &mut patches)?;
+            PyResult::Ok(patches)
+        }}
+    }
+
+    // returns a proxy for the object of the given document proxy in this transaction,
+    // so proxies obtained before the transaction was opened can be used to modify the document
+    fn view_of(&self, py: Python<'_>, document: &Document) -> PyResult<PyObject> {
+        if !Arc::ptr_eq(&self.automerge, &document.automerge) {
+            return Err(PyValueError::new_err(
+                "the proxy belongs to a different document than the transaction",
+            ));
+        }
+        let ty = {
+            with_transaction! {self, |tx| {
+                tx.object_type(document.obj_id.clone()).map_err(AutomergeError::AutomergeError)
+            }}
+        }?;
+        DocumentTransaction::for_subfield(
+            py,
+            self.automerge.clone(),
+            self.transaction.clone(),
+            ty,
+            document.obj_id.clone(),
+            None,
+        )
+    }
+
+    // the hash of the commited change, None before the commit and for empty commits.
+    // Cheaper than get_change() if only the hash is needed
+    #[getter]
+    fn change_hash(&self) -> Option<PyChangeHash> {
+        let change_hash = self.transaction.lock().unwrap().change_hash;
+        change_hash.map(PyChangeHash)
+    }
+
+    fn get_change(&self) -> PyResult<Option<Change>> {
+        let change_hash = self.transaction.lock().unwrap().change_hash;
+        if let Some(hash) = change_hash {
+            with_doc!(self, |doc| {
                 PyResult::Ok(doc.get_change_by_hash(&hash).map(|change| Change {
                     change: change.clone(),
                 }))
@@ -512,6 +2037,24 @@ impl CounterTransaction {
 // TODO(robin): prevent this from having __len__?
 #[pymethods]
 impl CounterTransaction {
+    // reads the current value, including increments made in this transaction
+    fn get(mut slf: PyRefMut<'_, Self>) -> PyResult<i64> {
+        let prop = slf.prop.clone();
+        let super_ = slf.as_mut();
+        with_transaction! {super_, |tx| {
+            if let Some((Value::Scalar(s), _)) = get(tx, super_.obj_id.clone(), prop.clone(), None)? {
+                if let ScalarValue::Counter(counter) = &*s {
+                    return Ok(counter.into());
+                }
+            }
+            Err(PyTypeError::new_err(format!("{prop} is no longer a counter")))
+        }}
+    }
+
+    fn __int__(slf: PyRefMut<'_, Self>) -> PyResult<i64> {
+        CounterTransaction::get(slf)
+    }
+
     fn increment(mut slf: PyRefMut<'_, Self>, py: Python<'_>, increment: i64) -> PyResult<()> {
         let prop = slf.prop.clone();
         let super_ = slf.as_mut();
@@ -529,24 +2072,131 @@ pub struct MappingTransaction;
 #[pymethods]
 impl MappingTransaction {
     fn __getitem__(slf: PyRefMut<'_, Self>, py: Python<'_>, name: &'_ str) -> PyResult<PyObject> {
-        MappingTransaction::__getattr__(slf, py, name)
+        slf.as_ref().ensure_live()?;
+        slf.as_ref()
+            .get_key(py, name)?
+            .ok_or_else(|| PyKeyError::new_err(name.to_string()))
+    }
+
+    fn __getattr__(slf: PyRefMut<'_, Self>, py: Python<'_>, name: &'_ str) -> PyResult<PyObject> {
+        if is_dunder(name) {
+            return Err(PyAttributeError::new_err(name.to_string()));
+        }
+        let super_ = slf.as_ref();
+        super_.ensure_live()?;
+        super_
+            .get_key(py, name)?
+            .ok_or_else(|| PyAttributeError::new_err(format!("{name}{}", super_.location())))
+    }
+
+    fn get_all(
+        slf: PyRefMut<'_, Self>,
+        py: Python<'_>,
+        name: &'_ str,
+    ) -> PyResult<Vec<(PyObject, String)>> {
+        slf.as_ref().get_conflicts(py, name.into())
+    }
+
+    fn __contains__(mut slf: PyRefMut<'_, Self>, name: &'_ str) -> PyResult<bool> {
+        let super_ = slf.as_mut();
+        with_transaction! {super_, |tx| {
+            PyResult::Ok(get(tx, super_.obj_id.clone(), name, None)?.is_some())
+        }}
+    }
+
+    fn keys(mut slf: PyRefMut<'_, Self>) -> PyResult<Vec<String>> {
+        let super_ = slf.as_mut();
+        with_transaction! {super_, |tx| {
+            PyResult::Ok(keys(tx, super_.obj_id.clone(), None))
+        }}
+    }
+
+    fn values(slf: PyRefMut<'_, Self>, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        Ok(MappingTransaction::items(slf, py)?
+            .into_iter()
+            .map(|(_, value)| value)
+            .collect())
+    }
+
+    fn items(mut slf: PyRefMut<'_, Self>, py: Python<'_>) -> PyResult<Vec<(String, PyObject)>> {
+        let super_ = slf.as_mut();
+        with_transaction! {super_, |tx| {
+            keys(tx, super_.obj_id.clone(), None).into_iter().map(|key| {
+                let value = read_value(py, tx, super_.obj_id.clone(), &key, None, |ty, obj_id| {
+                    DocumentTransaction::for_subfield(py, super_.automerge.clone(), super_.transaction.clone(), ty, obj_id, None)
+                },
+                Some(|_| CounterTransaction::new(py, super_, key.as_str()))
+                )?;
+                Ok((key, value))
+            }).collect::<PyResult<Vec<_>>>()
+        }}
     }
 
-    fn __getattr__(
+    // returns the value of the key, writing the default first if the key does not exist
+    #[pyo3(signature = (name, default=None))]
+    fn setdefault(
         mut slf: PyRefMut<'_, Self>,
         py: Python<'_>,
         name: &'_ str,
+        default: Option<AutomergeValue<'_>>,
     ) -> PyResult<PyObject> {
         let super_ = slf.as_mut();
         with_transaction! {super_, |tx| {
-            read_value(py, tx, super_.obj_id.clone(), name, |ty, obj_id| {
+            if get(tx, super_.obj_id.clone(), name, None)?.is_none() {
+                apply_value(tx, Some(&super_.transaction), super_.obj_id.clone(), name, default.unwrap_or(AutomergeValue::Null(None {})))?;
+            }
+            read_value(py, tx, super_.obj_id.clone(), name, None, |ty, obj_id| {
                 DocumentTransaction::for_subfield(py, super_.automerge.clone(), super_.transaction.clone(), ty, obj_id, None)
             },
-            Some(|| CounterTransaction::new(py, super_, name))
+            Some(|_| CounterTransaction::new(py, super_, name))
             )
         }}
     }
 
+    // removes the key and returns its materialized value
+    #[pyo3(signature = (name, *default))]
+    fn pop(
+        mut slf: PyRefMut<'_, Self>,
+        py: Python<'_>,
+        name: &'_ str,
+        default: &PyTuple,
+    ) -> PyResult<PyObject> {
+        if default.len() > 1 {
+            return Err(PyTypeError::new_err(format!(
+                "pop expected at most 2 arguments, got {}",
+                default.len() + 1
+            )));
+        }
+        let super_ = slf.as_mut();
+        with_transaction! {super_, |tx| {
+            match get(tx, super_.obj_id.clone(), name, None)? {
+                Some((value, id)) => {
+                    let value = materialize(py, tx, value, id, None)?;
+                    tx.delete(super_.obj_id.clone(), name).map_err(AutomergeError::AutomergeError)?;
+                    Ok(value)
+                }
+                None => match default.get_item(0) {
+                    Ok(default) => Ok(default.into_py(py)),
+                    Err(_) => Err(PyKeyError::new_err(name.to_string())),
+                },
+            }
+        }}
+    }
+
+    // removes the last key and returns it together with its materialized value
+    fn popitem(mut slf: PyRefMut<'_, Self>, py: Python<'_>) -> PyResult<(String, PyObject)> {
+        let super_ = slf.as_mut();
+        with_transaction! {super_, |tx| {
+            let Some(name) = tx.keys(super_.obj_id.clone()).last() else {
+                return Err(PyKeyError::new_err("popitem(): dictionary is empty"));
+            };
+            let (value, id) = get(tx, super_.obj_id.clone(), name.as_str(), None)?.unwrap();
+            let value = materialize(py, tx, value, id, None)?;
+            tx.delete(super_.obj_id.clone(), name.as_str()).map_err(AutomergeError::AutomergeError)?;
+            PyResult::Ok((name, value))
+        }}
+    }
+
     fn __setitem__(
         slf: PyRefMut<'_, Self>,
         name: &'_ str,
@@ -561,8 +2211,9 @@ impl MappingTransaction {
         value: AutomergeValue<'_>,
     ) -> PyResult<()> {
         let super_ = slf.as_mut();
+        super_.ensure_live()?;
         with_transaction! {super_, |tx| {
-            apply_value(tx, super_.obj_id.clone(), name, value)
+            apply_value(tx, Some(&super_.transaction), super_.obj_id.clone(), name, value)
         }}
     }
 
@@ -572,6 +2223,7 @@ impl MappingTransaction {
 
     fn __delattr__(mut slf: PyRefMut<'_, Self>, name: &'_ str) -> PyResult<()> {
         let super_ = slf.as_mut();
+        super_.ensure_live()?;
         with_transaction! {super_, |tx| {
             tx.delete(super_.obj_id.clone(), name).map_err(AutomergeError::AutomergeError)
         }}
@@ -580,7 +2232,7 @@ impl MappingTransaction {
 
 #[derive(FromPyObject)]
 enum SliceOrIndex<'a> {
-    Index(usize),
+    Index(isize),
     Slice(&'a PySlice),
 }
 
@@ -593,12 +2245,85 @@ pub struct SequenceTransaction;
 // - append, clear, extend, index, count, insert, pop, remove, reverse?
 #[pymethods]
 impl SequenceTransaction {
+    // see Sequence.cursor
+    fn cursor(slf: PyRefMut<'_, Self>, index: isize) -> PyResult<Cursor> {
+        let super_ = slf.as_ref();
+        with_transaction! {super_, |tx| {
+            cursor(tx, &super_.obj_id, index, None)
+        }}
+    }
+
+    // see Sequence.cursor_position
+    #[pyo3(signature = (cursor, heads=None))]
+    fn cursor_position(
+        slf: PyRefMut<'_, Self>,
+        cursor: &Cursor,
+        heads: Option<Vec<HashArg<'_>>>,
+    ) -> PyResult<usize> {
+        let heads = heads.map(extract_heads).transpose()?;
+        let super_ = slf.as_ref();
+        with_transaction! {super_, |tx| {
+            cursor_position(tx, &super_.obj_id, cursor, heads.as_deref())
+        }}
+    }
+
+    fn __contains__(slf: PyRefMut<'_, Self>, py: Python<'_>, value: &PyAny) -> PyResult<bool> {
+        let super_ = slf.as_ref();
+        with_transaction! {super_, |tx| {
+            for item in list_values(tx, super_.obj_id.clone(), None) {
+                if scalar_eq(py, &item, value)? {
+                    return Ok(true);
+                }
+            }
+            PyResult::Ok(false)
+        }}
+    }
+
+    fn get_all(
+        slf: PyRefMut<'_, Self>,
+        py: Python<'_>,
+        index: usize,
+    ) -> PyResult<Vec<(PyObject, String)>> {
+        slf.as_ref().get_conflicts(py, index.into())
+    }
+
+    #[pyo3(signature = (value, start=0, stop=None))]
+    fn index(
+        slf: PyRefMut<'_, Self>,
+        py: Python<'_>,
+        value: &PyAny,
+        start: isize,
+        stop: Option<isize>,
+    ) -> PyResult<usize> {
+        let super_ = slf.as_ref();
+        with_transaction! {super_, |tx| {
+            let values = list_values(tx, super_.obj_id.clone(), None);
+            position(py, &values, value, start, stop)?.ok_or_else(|| PyValueError::new_err("x not in list"))
+        }}
+    }
+
+    fn count(slf: PyRefMut<'_, Self>, py: Python<'_>, value: &PyAny) -> PyResult<usize> {
+        let super_ = slf.as_ref();
+        with_transaction! {super_, |tx| {
+            count(py, &list_values(tx, super_.obj_id.clone(), None), value)
+        }}
+    }
+
+    fn __iter__(slf: PyRefMut<'_, Self>) -> PyResult<SequenceTransactionIterator> {
+        slf.as_ref().ensure_live()?;
+        Ok(SequenceTransactionIterator {
+            transaction: slf.as_ref().clone(),
+            index: 0,
+        })
+    }
+
     fn __getitem__(
         slf: PyRefMut<'_, Self>,
         py: Python<'_>,
         mut index: isize,
     ) -> PyResult<PyObject> {
         let super_ = slf.as_ref();
+        super_.ensure_live()?;
         with_transaction! {super_, |tx| {
             let length = tx.length(super_.obj_id.clone());
             if index < 0 {
@@ -610,13 +2335,16 @@ impl SequenceTransaction {
             }
             let index: usize = index.try_into().unwrap();
             if index < length {
-                read_value(py, tx, super_.obj_id.clone(), index, |ty, obj_id| {
+                read_value(py, tx, super_.obj_id.clone(), index, None, |ty, obj_id| {
                     Ok(DocumentTransaction::for_subfield(py, super_.automerge.clone(), super_.transaction.clone(), ty, obj_id, None)?.into_py(py))
                 },
-                Some(|| CounterTransaction::new(py, super_, index))
+                Some(|_| CounterTransaction::new(py, super_, index))
                 )
             } else {
-                Err(PyIndexError::new_err(format!("index {index} is greater than length {length}")))
+                Err(PyIndexError::new_err(format!(
+                    "index {index} is greater than length {length}{}",
+                    location(tx, &super_.obj_id, None)
+                )))
             }
         }}
     }
@@ -627,14 +2355,17 @@ impl SequenceTransaction {
         value: AutomergeValue<'_>,
     ) -> PyResult<()> {
         let super_ = slf.as_mut();
+        super_.ensure_live()?;
         with_transaction! {super_, |tx| {
             match index_or_slice {
                 SliceOrIndex::Index(index) => {
                     let length = tx.length(super_.obj_id.clone());
-                    if index == length { // Setting the n+1'th item is the same as appending, so we add a dummy element
-                        tx.splice(super_.obj_id.clone(), length, 0, [ScalarValue::Null]).map_err(AutomergeError::AutomergeError)?;
+                    let index = sequence_insert_index(index, length, "list")?;
+                    if index == length { // Setting the n+1'th item is the same as appending
+                        Ok(insert_value(tx, Some(&super_.transaction), super_.obj_id.clone(), index, value)?)
+                    } else {
+                        Ok(apply_value(tx, Some(&super_.transaction), super_.obj_id.clone(), index, value)?)
                     }
-                    Ok(apply_value(tx, super_.obj_id.clone(), index, value)?)
                 },
                 SliceOrIndex::Slice(slice) => {
                     let length = tx.length(super_.obj_id.clone());
@@ -649,17 +2380,17 @@ impl SequenceTransaction {
                                 ))
                             } else {
 
-                                // for step == 1, we "replace" the old slice with a new sequence and the lenght could change
-                                // we insert dummy values for the new sequence
+                                // for step == 1, we "replace" the old slice with a new sequence and the lenght could change,
+                                // so the old values are deleted and the new ones inserted
                                 // for step != 1, we simply replace the values
                                 if slice.step == 1 {
-                                    tx.splice(super_.obj_id.clone(), slice.start as usize, slice.slicelength, std::iter::repeat(ScalarValue::Null).take(sequence_len)).map_err(AutomergeError::AutomergeError)?;
+                                    tx.splice(super_.obj_id.clone(), slice.start as usize, slice.slicelength, std::iter::empty()).map_err(AutomergeError::AutomergeError)?;
+                                    return insert_values(tx, Some(&super_.transaction), super_.obj_id.clone(), slice.start as usize, s);
                                 }
 
-                                // now simply write the values
                                 for (i, elem) in s.iter()?.enumerate() {
                                     let i = (slice.start + (i as isize) * slice.step) as usize;
-                                    apply_value(tx, super_.obj_id.clone(), i, elem?.extract()?)?;
+                                    apply_value(tx, Some(&super_.transaction), super_.obj_id.clone(), i, elem?.extract()?)?;
                                 }
                                 Ok(())
 
@@ -675,40 +2406,93 @@ impl SequenceTransaction {
         }}
     }
 
-    fn __delitem__(mut slf: PyRefMut<'_, Self>, index: usize) -> PyResult<()> {
+    fn __delitem__(mut slf: PyRefMut<'_, Self>, index: isize) -> PyResult<()> {
         let super_ = slf.as_mut();
+        super_.ensure_live()?;
         with_transaction! {super_, |tx| {
+            let index = sequence_index(index, tx.length(super_.obj_id.clone()), "list")?;
             tx.delete(super_.obj_id.clone(), index).map_err(AutomergeError::AutomergeError)
         }}
     }
 
+    // removes the first element equal to the given value
+    fn remove(mut slf: PyRefMut<'_, Self>, py: Python<'_>, value: &PyAny) -> PyResult<()> {
+        let super_ = slf.as_mut();
+        with_transaction! {super_, |tx| {
+            let values = list_values(tx, super_.obj_id.clone(), None);
+            match position(py, &values, value, 0, None)? {
+                Some(index) => Ok(tx.delete(super_.obj_id.clone(), index).map_err(AutomergeError::AutomergeError)?),
+                None => Err(PyValueError::new_err("x not in list")),
+            }
+        }}
+    }
+
+    // removes delete_count elements starting at index (fewer if the list ends before) and inserts values there,
+    // runs of scalar values are inserted with a single splice
+    fn splice(
+        mut slf: PyRefMut<'_, Self>,
+        index: isize,
+        delete_count: usize,
+        values: &PyAny,
+    ) -> PyResult<()> {
+        let super_ = slf.as_mut();
+        with_transaction! {super_, |tx| {
+            let length = tx.length(super_.obj_id.clone());
+            let index = sequence_insert_index(index, length, "list")?;
+            let delete_count = delete_count.min(length - index);
+            tx.splice(super_.obj_id.clone(), index, delete_count as isize, std::iter::empty())
+                .map_err(AutomergeError::AutomergeError)?;
+            insert_values(tx, Some(&super_.transaction), super_.obj_id.clone(), index, values)?;
+            PyResult::Ok(())
+        }}
+    }
+
     fn append(mut slf: PyRefMut<'_, Self>, value: AutomergeValue<'_>) -> PyResult<()> {
         let super_ = slf.as_mut();
         with_transaction! {super_, |tx| {
                 let length = tx.length(super_.obj_id.clone());
-                // Setting the n+1'th item is the same as appending, so we add a dummy element
-                tx.splice(super_.obj_id.clone(), length, 0, [ScalarValue::Null]).map_err(AutomergeError::AutomergeError)?;
-                apply_value(tx, super_.obj_id.clone(), length, value)
+                insert_value(tx, Some(&super_.transaction), super_.obj_id.clone(), length, value)
             }
         }
     }
 }
 
+// Iterates over the values of a list inside a transaction
+#[pyclass]
+pub struct SequenceTransactionIterator {
+    transaction: DocumentTransaction,
+    index: usize,
+}
+
+#[pymethods]
+impl SequenceTransactionIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(mut slf: PyRefMut<'_, Self>, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let value = slf.transaction.get_index(py, slf.index)?;
+        slf.index += 1;
+        Ok(value)
+    }
+}
+
 // special sub class for transactions on Text
+// all indices and lengths are in unicode code points, like the indices of a python str:
+// automerge (built without the utf8-indexing feature and outside of wasm) stores text as chars,
+// so python indices can be passed to splice_text without conversion
 #[pyclass(extends=DocumentTransaction, sequence)]
 pub struct TextTransaction;
 
 #[pymethods]
 impl TextTransaction {
-    fn __getitem__(slf: PyRefMut<'_, Self>, py: Python<'_>, index: usize) -> PyResult<String> {
+    fn __getitem__(slf: PyRefMut<'_, Self>, index: isize) -> PyResult<String> {
         let super_ = slf.as_ref();
+        super_.ensure_live()?;
         with_transaction! {super_, |tx| {
-            let length = tx.length(super_.obj_id.clone());
-            if index < length {
-                Ok(tx.get(super_.obj_id.clone(), index).map_err(AutomergeError::AutomergeError)?.unwrap().0.into_string().unwrap())
-            } else {
-                Err(PyIndexError::new_err(format!("index {index} is greater than length {length}")))
-            }
+            let index = text_index(index, tx.length(super_.obj_id.clone()))?;
+            let value = tx.get(super_.obj_id.clone(), index).map_err(AutomergeError::AutomergeError)?;
+            PyResult::Ok(value.unwrap().0.into_string().unwrap())
         }}
     }
 
@@ -718,20 +2502,19 @@ impl TextTransaction {
         value: &str,
     ) -> PyResult<()> {
         let super_ = slf.as_mut();
+        super_.ensure_live()?;
         with_transaction! {super_, |tx| {
             let value_len = value.chars().count();
             match index_or_slice {
                 SliceOrIndex::Index(index) => {
-                    // TODO(robin): do we get unicode length mismatch here?
-                    // (python str.len() vs automerge str length)
-                    // also python index vs rust index
-                    Ok(tx.splice_text(super_.obj_id.clone(), index, value_len as isize, value).map_err(AutomergeError::AutomergeError)?)
+                    // replaces exactly the character at index, value may be longer (or empty)
+                    let index = text_index(index, tx.length(super_.obj_id.clone()))?;
+                    Ok(tx.splice_text(super_.obj_id.clone(), index, 1, value).map_err(AutomergeError::AutomergeError)?)
                 },
                 SliceOrIndex::Slice(slice) => {
                     let length = tx.length(super_.obj_id.clone());
                     let slice = slice.indices(length as _)?;
 
-                    // TODO(robin): same here
                     if slice.step != 1 && (slice.slicelength as usize) != value_len {
                         Err(PyValueError::new_err(
                             format!("attempt to assign sequence of size {} to extended slice of size {}", value_len, slice.slicelength)
@@ -768,306 +2551,3327 @@ impl TextTransaction {
         }}
     }
 
-    fn __delitem__(mut slf: PyRefMut<'_, Self>, index: usize) -> PyResult<()> {
+    fn insert(mut slf: PyRefMut<'_, Self>, index: isize, value: &str) -> PyResult<()> {
         let super_ = slf.as_mut();
         with_transaction! {super_, |tx| {
-            tx.splice_text(super_.obj_id.clone(), index, 1, "").map_err(AutomergeError::AutomergeError)
+            let index = text_insert_index(index, tx.length(super_.obj_id.clone()))?;
+            tx.splice_text(super_.obj_id.clone(), index, 0, value).map_err(AutomergeError::AutomergeError)
         }}
     }
-}
 
-macro_rules! match_value {
-    ($value:expr,
-        Scalar($scalar:ident) => $scalar_handler:tt,
-        Sequence($sequence:ident) => $sequence_handler:tt,
-        Mapping($mapping:ident) => $mapping_handler:tt,
-        Text($text:ident) => $text_handler:tt,
-    ) => {
-        use AutomergeValue::*;
-        match_value!(
-            @gen_arms, $value, $scalar, $scalar_handler, Bytes, Str, Int, Uint, F64, Counter, Boolean, Null : rest, {
-                match_value!(@gen_arms, rest, $sequence, $sequence_handler, Sequence : rest, {
-                    match_value!(@gen_arms, rest, $mapping, $mapping_handler, Mapping : rest, {
-                        match_value!(@gen_arms, rest, $text, $text_handler, Text : _rest, {
-                            unreachable!();
-                        })
-                    })
-                })
-            }
-        )
-    };
-    (@gen_arms, $value:expr, $name:ident, $handler:tt, $($cases:ident),* : $other:ident, $rest_handler:tt) => {
-        match $value {
-            $($cases($name) => $handler)*,
-            $other => $rest_handler,
-        }
+    // replaces the content with new_text by splicing in only the characters that changed,
+    // so concurrent edits to the same text merge instead of replacing each other
+    fn update(mut slf: PyRefMut<'_, Self>, new_text: &str) -> PyResult<()> {
+        let super_ = slf.as_mut();
+        with_transaction! {super_, |tx| {
+            tx.update_text(&super_.obj_id, new_text).map_err(AutomergeError::AutomergeError)
+        }}
     }
-}
 
-#[derive(FromPyObject, Debug)]
-struct PyBytesNT<'a>(&'a PyBytes);
-
-impl<'a> From<PyBytesNT<'a>> for ScalarValue {
-    fn from(bytes: PyBytesNT<'a>) -> Self {
-        ScalarValue::Bytes(bytes.0.as_bytes().to_vec())
+    // marks the characters from start to end (exclusive) with name = value,
+    // expand decides whether text inserted at the edges of the range is marked too
+    #[pyo3(signature = (start, end, name, value, expand="grow"))]
+    fn mark(
+        mut slf: PyRefMut<'_, Self>,
+        start: isize,
+        end: isize,
+        name: &str,
+        value: MarkValue,
+        expand: &str,
+    ) -> PyResult<()> {
+        let expand = expand_mark(expand)?;
+        let super_ = slf.as_mut();
+        with_transaction! {super_, |tx| {
+            let (start, end) = mark_range(start, end, tx.length(super_.obj_id.clone()))?;
+            let mark = Mark::new(name.to_owned(), value.0, start, end);
+            tx.mark(&super_.obj_id, mark, expand).map_err(AutomergeError::AutomergeError)
+        }}
     }
-}
-
-#[derive(Debug)]
-struct None {}
 
-impl<'a> FromPyObject<'a> for None {
-    fn extract(obj: &'a PyAny) -> PyResult<Self> {
-        if obj.as_ptr() == unsafe { pyo3::ffi::Py_None() } {
-            Ok(None {})
-        } else {
-            return Err(PyException::new_err("None is not None"));
-        }
+    #[pyo3(signature = (start, end, name, expand="grow"))]
+    fn unmark(
+        mut slf: PyRefMut<'_, Self>,
+        start: isize,
+        end: isize,
+        name: &str,
+        expand: &str,
+    ) -> PyResult<()> {
+        let expand = expand_mark(expand)?;
+        let super_ = slf.as_mut();
+        with_transaction! {super_, |tx| {
+            let (start, end) = mark_range(start, end, tx.length(super_.obj_id.clone()))?;
+            tx.unmark(&super_.obj_id, name, start, end, expand).map_err(AutomergeError::AutomergeError)
+        }}
     }
-}
 
-impl From<None> for ScalarValue {
-    fn from(counter: None) -> ScalarValue {
-        ScalarValue::Null
+    // inserts a block marker (e.g. the start of a paragraph or list item) with the attributes attrs
+    // at index, the text after it becomes part of the new block
+    #[pyo3(signature = (index, attrs=None))]
+    fn split_block(
+        mut slf: PyRefMut<'_, Self>,
+        index: isize,
+        attrs: Option<&PyMapping>,
+    ) -> PyResult<()> {
+        let super_ = slf.as_mut();
+        super_.ensure_live()?;
+        with_transaction! {super_, |tx| {
+            let index = text_insert_index(index, tx.length(super_.obj_id.clone()))?;
+            let block = tx.split_block(&super_.obj_id, index).map_err(AutomergeError::AutomergeError)?;
+            write_block_attrs(tx, &super_.transaction, &block, attrs)
+        }}
     }
+
+    // replaces the attributes of the block marker at index
+    fn update_block(mut slf: PyRefMut<'_, Self>, index: isize, attrs: &PyMapping) -> PyResult<()> {
+        let super_ = slf.as_mut();
+        super_.ensure_live()?;
+        with_transaction! {super_, |tx| {
+            let index = text_index(index, tx.length(super_.obj_id.clone()))?;
+            let block = tx.replace_block(&super_.obj_id, index).map_err(AutomergeError::AutomergeError)?;
+            write_block_attrs(tx, &super_.transaction, &block, Some(attrs))
+        }}
+    }
+
+    // removes the block marker at index, joining its text with the block before it
+    fn join_block(mut slf: PyRefMut<'_, Self>, index: isize) -> PyResult<()> {
+        let super_ = slf.as_mut();
+        super_.ensure_live()?;
+        with_transaction! {super_, |tx| {
+            let index = text_index(index, tx.length(super_.obj_id.clone()))?;
+            tx.join_block(&super_.obj_id, index).map_err(AutomergeError::AutomergeError)
+        }}
+    }
+
+    // the text split into runs of text with the same marks and the block markers between them, see TextView.spans
+    fn spans(slf: PyRefMut<'_, Self>, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        let super_ = slf.as_ref();
+        super_.ensure_live()?;
+        with_transaction! {super_, |tx| {
+            text_spans(py, tx, &super_.obj_id, None)
+        }}
+    }
+
+    fn append(mut slf: PyRefMut<'_, Self>, value: &str) -> PyResult<()> {
+        let super_ = slf.as_mut();
+        with_transaction! {super_, |tx| {
+            let length = tx.length(super_.obj_id.clone());
+            tx.splice_text(super_.obj_id.clone(), length, 0, value).map_err(AutomergeError::AutomergeError)
+        }}
+    }
+
+    fn __delitem__(mut slf: PyRefMut<'_, Self>, index_or_slice: SliceOrIndex<'_>) -> PyResult<()> {
+        let super_ = slf.as_mut();
+        super_.ensure_live()?;
+        with_transaction! {super_, |tx| {
+            let length = tx.length(super_.obj_id.clone());
+            match index_or_slice {
+                SliceOrIndex::Index(index) => {
+                    let index = text_index(index, length)?;
+                    tx.splice_text(super_.obj_id.clone(), index, 1, "").map_err(AutomergeError::AutomergeError)?;
+                }
+                SliceOrIndex::Slice(slice) => {
+                    let slice = slice.indices(length as _)?;
+                    if slice.step == 1 {
+                        tx.splice_text(super_.obj_id.clone(), slice.start as usize, slice.slicelength, "")
+                            .map_err(AutomergeError::AutomergeError)?;
+                    } else {
+                        // delete from the back, so the remaining positions do not shift
+                        let mut positions: Vec<usize> = (0..slice.slicelength)
+                            .map(|i| (slice.start + i * slice.step) as usize)
+                            .collect();
+                        positions.sort_unstable_by(|a, b| b.cmp(a));
+                        for position in positions {
+                            tx.splice_text(super_.obj_id.clone(), position, 1, "").map_err(AutomergeError::AutomergeError)?;
+                        }
+                    }
+                }
+            }
+            PyResult::Ok(())
+        }}
+    }
+}
+
+macro_rules! match_value {
+    ($value:expr,
+        Scalar($scalar:ident) => $scalar_handler:tt,
+        Sequence($sequence:ident) => $sequence_handler:tt,
+        Mapping($mapping:ident) => $mapping_handler:tt,
+        Text($text:ident) => $text_handler:tt,
+    ) => {
+        use AutomergeValue::*;
+        match_value!(
+            @gen_arms, $value, $scalar, $scalar_handler, Bytes, Str, Int, Uint, F64, Timestamp, Counter, Unknown, Boolean, Null : rest, {
+                match_value!(@gen_arms, rest, $sequence, $sequence_handler, Sequence : rest, {
+                    match_value!(@gen_arms, rest, $mapping, $mapping_handler, Mapping : rest, {
+                        match_value!(@gen_arms, rest, $text, $text_handler, Text : _rest, {
+                            unreachable!();
+                        })
+                    })
+                })
+            }
+        )
+    };
+    (@gen_arms, $value:expr, $name:ident, $handler:tt, $($cases:ident),* : $other:ident, $rest_handler:tt) => {
+        match $value {
+            $($cases($name) => $handler)*,
+            $other => $rest_handler,
+        }
+    }
+}
+
+// bytes or anything else implementing the buffer protocol with byte sized items
+// (bytearray, memoryview, numpy uint8 arrays, ...)
+#[derive(Debug)]
+struct PyBytesNT(Vec<u8>);
+
+impl<'a> FromPyObject<'a> for PyBytesNT {
+    fn extract(obj: &'a PyAny) -> PyResult<Self> {
+        match obj.downcast::<PyBytes>() {
+            Ok(bytes) => Ok(PyBytesNT(bytes.as_bytes().to_vec())),
+            Err(_) => Ok(PyBytesNT(PyBuffer::<u8>::get(obj)?.to_vec(obj.py())?)),
+        }
+    }
+}
+
+impl From<PyBytesNT> for ScalarValue {
+    fn from(bytes: PyBytesNT) -> Self {
+        ScalarValue::Bytes(bytes.0)
+    }
+}
+
+// a timezone aware datetime.datetime (or a raw Timestamp), stored as milliseconds since the unix epoch
+// naive datetimes are rejected, as their meaning depends on the local timezone
+#[derive(Debug)]
+pub struct Timestamp(i64);
+
+impl<'a> FromPyObject<'a> for Timestamp {
+    fn extract(obj: &'a PyAny) -> PyResult<Self> {
+        let py = obj.py();
+        if let Ok(timestamp) = obj.extract::<PyRef<'_, PyTimestamp>>() {
+            return Ok(Timestamp(timestamp.0));
+        }
+        let datetime: &PyDateTime = obj.downcast()?;
+        if datetime.get_tzinfo().is_none() {
+            return Err(PyValueError::new_err(
+                "naive datetimes are not supported, attach a timezone (e.g. datetime.timezone.utc)",
+            ));
+        }
+        let delta: &PyDelta = datetime
+            .call_method1("__sub__", (unix_epoch(py)?,))?
+            .downcast()?;
+        Ok(Timestamp(
+            i64::from(delta.get_days()) * 86_400_000
+                + i64::from(delta.get_seconds()) * 1000
+                + i64::from(delta.get_microseconds()) / 1000,
+        ))
+    }
+}
+
+impl From<Timestamp> for ScalarValue {
+    fn from(timestamp: Timestamp) -> ScalarValue {
+        ScalarValue::Timestamp(timestamp.0)
+    }
+}
+
+fn unix_epoch(py: Python<'_>) -> PyResult<&PyDateTime> {
+    PyDateTime::new(py, 1970, 1, 1, 0, 0, 0, 0, Some(timezone_utc(py)))
+}
+
+// converts milliseconds since the unix epoch into a utc datetime.datetime
+fn timestamp_to_datetime(py: Python<'_>, timestamp: i64) -> PyResult<PyObject> {
+    let days = i32::try_from(timestamp.div_euclid(86_400_000))
+        .map_err(|_| PyValueError::new_err("timestamp out of range"))?;
+    let millis = timestamp.rem_euclid(86_400_000) as i32;
+    let delta = PyDelta::new(py, days, millis / 1000, (millis % 1000) * 1000, false)?;
+    Ok(unix_epoch(py)?
+        .call_method1("__add__", (delta,))?
+        .into_py(py))
+}
+
+// the time of a commit, either a timezone aware datetime.datetime or milliseconds since the unix epoch
+#[derive(Debug)]
+pub struct CommitTime(i64);
+
+impl<'a> FromPyObject<'a> for CommitTime {
+    fn extract(obj: &'a PyAny) -> PyResult<Self> {
+        if obj.is_instance_of::<PyDateTime>() {
+            Ok(CommitTime(Timestamp::extract(obj)?.0))
+        } else if let Ok(millis) = obj.extract::<i64>() {
+            Ok(CommitTime(millis))
+        } else if let Ok(millis) = obj.extract::<f64>() {
+            if !millis.is_finite() || millis.abs() >= i64::MAX as f64 {
+                return Err(PyValueError::new_err("commit time out of range"));
+            }
+            Ok(CommitTime(millis as i64))
+        } else {
+            Err(PyTypeError::new_err(format!(
+                "expected a datetime or milliseconds since the unix epoch as commit time, got {}",
+                obj.get_type().name()?
+            )))
+        }
+    }
+}
+
+// returns the raw milliseconds since the unix epoch automerge stores for a datetime (or Timestamp)
+#[pyfunction]
+pub fn timestamp_millis(datetime: Timestamp) -> i64 {
+    datetime.0
+}
+
+// A timestamp as the raw milliseconds since the unix epoch automerge stores.
+// Timestamps are read as this if they don't fit into a datetime.datetime or within raw_timestamps(),
+// writing it stores a timestamp again
+#[pyclass(name = "Timestamp")]
+#[derive(Clone, Debug)]
+pub struct PyTimestamp(i64);
+
+#[pymethods]
+impl PyTimestamp {
+    #[new]
+    fn new(millis: i64) -> Self {
+        PyTimestamp(millis)
+    }
+
+    #[getter]
+    fn millis(&self) -> i64 {
+        self.0
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Timestamp({})", self.0)
+    }
+
+    fn __eq__(&self, other: &PyAny) -> bool {
+        other
+            .extract::<PyRef<'_, PyTimestamp>>()
+            .map_or(false, |other| self.0 == other.0)
+    }
+
+    fn __hash__(&self) -> isize {
+        self.0 as isize
+    }
+}
+
+thread_local! {
+    // set while the current thread is within raw_timestamps()
+    static RAW_TIMESTAMPS: Cell<bool> = const { Cell::new(false) };
+}
+
+// Within `with raw_timestamps():`, timestamps read in the current thread are returned as Timestamp
+// (the raw milliseconds) instead of datetime.datetime
+#[pyclass]
+pub struct RawTimestamps {
+    previous: bool,
+}
+
+#[pymethods]
+impl RawTimestamps {
+    fn __enter__(mut slf: PyRefMut<'_, Self>) -> PyRefMut<'_, Self> {
+        slf.previous = RAW_TIMESTAMPS.with(|raw| raw.replace(true));
+        slf
+    }
+
+    fn __exit__(&self, _ty: Option<&PyAny>, _value: Option<&PyAny>, _traceback: Option<&PyAny>) {
+        RAW_TIMESTAMPS.with(|raw| raw.set(self.previous));
+    }
+}
+
+#[pyfunction]
+pub fn raw_timestamps() -> RawTimestamps {
+    RawTimestamps { previous: false }
+}
+
+#[derive(Debug)]
+struct None {}
+
+impl<'a> FromPyObject<'a> for None {
+    fn extract(obj: &'a PyAny) -> PyResult<Self> {
+        if obj.is_none() {
+            Ok(None {})
+        } else {
+            Err(PyTypeError::new_err("value is not None"))
+        }
+    }
+}
+
+// None is written as an explicit Null value
+impl From<None> for ScalarValue {
+    fn from(_: None) -> ScalarValue {
+        ScalarValue::Null
+    }
+}
+
+// TODO(robin): allow arbitrary things and use .__dict__?
+// These are the values we support for conversion into Automerge values
+#[derive(Debug, FromPyObject)]
+enum AutomergeValue<'a> {
+    Boolean(bool),
+    Str(&'a str),
+    // needs to come before Int, as counters support __index__
+    Counter(Counter),
+    Int(i64),
+    Uint(u64),
+    // ints which neither fit into a i64 nor a u64, these are rejected when writing
+    BigInt(&'a PyLong),
+    F64(f64),
+    Timestamp(Timestamp),
+    Unknown(Unknown),
+    // needs to come before Text, Mapping and Sequence, proxies are copied with all their content
+    Proxy(ProxyValue),
+    Text(TextValue),
+    Bytes(PyBytesNT),
+    Mapping(&'a PyMapping),
+    // lists, tuples and other sequences as well as sets, see sequence_value
+    Sequence(#[pyo3(from_py_with = "sequence_value")] &'a PySequence),
+    Null(None),
+}
+
+// the elements of a value written as a list. Sets and frozensets are written as sorted lists, so every
+// peer writing the same set creates the same list, sets with elements that can't be compared raise a TypeError.
+// str and bytes are never written as a list of their characters or bytes
+fn sequence_value(value: &PyAny) -> PyResult<&PySequence> {
+    let py = value.py();
+    if value.is_instance_of::<PyString>()
+        || value.is_instance_of::<PyBytes>()
+        || value.is_instance_of::<PyByteArray>()
+    {
+        Err(PyTypeError::new_err(format!(
+            "{} is not written as a list",
+            value.get_type().name()?
+        )))
+    } else if value.is_instance_of::<PySet>() || value.is_instance_of::<PyFrozenSet>() {
+        let sorted = py
+            .import("builtins")?
+            .getattr("sorted")?
+            .call1((value,))
+            .map_err(|err| {
+                PyTypeError::new_err(format!(
+                    "sets are written as sorted lists, but the elements can't be sorted: {}",
+                    err.value(py)
+                ))
+            })?;
+        Ok(sorted.downcast()?)
+    } else {
+        Ok(value.downcast()?)
+    }
+}
+
+// This converts from a python value to a Automerge value and creates the appropriate transaction to write that value to the document
+// Errors say where in the document the failing value would have been written, e.g. "at items[3].meta.tags[0]"
+// writing is the transaction tx belongs to (None for transactions not visible to python)
+fn apply_value(
+    tx: &mut Tx,
+    writing: Option<&TransactionLock>,
+    obj: impl AsRef<ObjId>,
+    prop: impl Into<Prop>,
+    value: AutomergeValue,
+) -> Result<(), PyErr> {
+    let obj = obj.as_ref();
+    let mut path = vec![prop.into()];
+    apply_value_at(tx, writing, obj, &mut path, value, false)
+        .map_err(|err| locate_error(tx, obj, &path, err))
+}
+
+// like apply_value, but inserts the value as a new element at index of a list instead of overwriting
+fn insert_value(
+    tx: &mut Tx,
+    writing: Option<&TransactionLock>,
+    obj: impl AsRef<ObjId>,
+    index: usize,
+    value: AutomergeValue,
+) -> Result<(), PyErr> {
+    let obj = obj.as_ref();
+    let mut path = vec![index.into()];
+    apply_value_at(tx, writing, obj, &mut path, value, true)
+        .map_err(|err| locate_error(tx, obj, &path, err))
+}
+
+// like apply_value, but inserts all values of an iterable into the list obj, starting at index
+fn insert_values(
+    tx: &mut Tx,
+    writing: Option<&TransactionLock>,
+    obj: impl AsRef<ObjId>,
+    index: usize,
+    values: &PyAny,
+) -> Result<(), PyErr> {
+    let obj = obj.as_ref();
+    let mut path = Vec::new();
+    insert_values_at(tx, writing, obj, index, values, &mut path)
+        .map_err(|err| locate_error(tx, obj, &path, err))
+}
+
+// adds the location of the value that failed to be written to obj at path to the error
+fn locate_error(tx: &Tx, obj: &ObjId, path: &[Prop], err: PyErr) -> PyErr {
+    let mut location = obj_path(tx, obj, None);
+    location.extend_from_slice(path);
+    Python::with_gil(|py| {
+        PyErr::from_type(
+            err.get_type(py),
+            format!("{} at {}", err.value(py), format_path(&location)),
+        )
+    })
+}
+
+// writes value at the last prop of path (inserting it there if insert is set and the prop is an index),
+// on errors path is left pointing to the failing value
+fn apply_value_at(
+    tx: &mut Tx,
+    writing: Option<&TransactionLock>,
+    obj: &ObjId,
+    path: &mut Vec<Prop>,
+    value: AutomergeValue,
+    insert: bool,
+) -> Result<(), PyErr> {
+    let prop = path.last().unwrap().clone();
+    let create_object = |tx: &mut Tx, ty| create_object(tx, obj, prop.clone(), ty, insert);
+    match value {
+        AutomergeValue::BigInt(int) => {
+            return Err(PyOverflowError::new_err(format!(
+                "int {int} does not fit into a 64 bit integer"
+            )));
+        }
+        AutomergeValue::Proxy(proxy) => {
+            let copy = match proxy {
+                ProxyValue::Copied(copy) => copy,
+                ProxyValue::Failed(err) => return Err(err),
+                // read everything before writing, the copy might be written into the object itself
+                ProxyValue::Transaction(proxy)
+                    if writing
+                        .map_or(false, |writing| Arc::ptr_eq(writing, &proxy.transaction)) =>
+                {
+                    copy_live_object(tx, &proxy.obj_id, None)?
+                }
+                ProxyValue::Transaction(proxy) => proxy.copy()?,
+            };
+            return Ok(write_copy(tx, obj, prop, copy, insert)?);
+        }
+        _ => {}
+    }
+    match_value!(value,
+        Scalar(s) => {
+            match (insert, prop) {
+                (true, Prop::Seq(index)) => tx.insert(obj, index, s),
+                (_, prop) => tx.put(obj, prop, s),
+            }.map_err(AutomergeError::AutomergeError)?;
+        },
+        Sequence(s) => {
+            let sequence_id = create_object(tx, ObjType::List).map_err(AutomergeError::AutomergeError)?;
+            insert_values_at(tx, writing, &sequence_id, 0, s, path)?;
+        },
+        Mapping(m) => {
+            let mapping_id = create_object(tx, ObjType::Map).map_err(AutomergeError::AutomergeError)?;
+            for entry in m.items()?.iter()? {
+                let (name, elem): (&PyAny, &PyAny) = entry?.extract()?;
+                let name: &str = name.extract().map_err(|_| {
+                    PyTypeError::new_err(format!("keys must be str, not {}", name.get_type().name().unwrap_or("?")))
+                })?;
+                path.push(name.into());
+                apply_value_at(tx, writing, &mapping_id, path, extract_value(elem)?, false)?;
+                path.pop();
+            }
+        },
+        Text(t) => {
+            let text_id = create_object(tx, ObjType::Text).map_err(AutomergeError::AutomergeError)?;
+            // overwrite the complete text
+            tx.splice_text(text_id, 0, 0, &t.0).map_err(AutomergeError::AutomergeError)?;
+        },
+    );
+
+    Ok(())
+
+    // put
+    // put_object
+    // insert
+    // insert_object
+    // increment
+    // delete
+    // splice
+    // splice_text
+}
+
+// creates a new object at prop of obj, inserting it there if insert is set and the prop is an index
+fn create_object(
+    tx: &mut Tx,
+    obj: &ObjId,
+    prop: Prop,
+    ty: ObjType,
+    insert: bool,
+) -> Result<ObjId, automerge::AutomergeError> {
+    match (insert, prop) {
+        (true, Prop::Seq(index)) => tx.insert_object(obj, index, ty),
+        (_, prop) => tx.put_object(obj, prop, ty),
+    }
+}
+
+// writes a copy read by copy_object at prop of obj (inserting it there if insert is set and the prop is an index)
+fn write_copy(
+    tx: &mut Tx,
+    obj: &ObjId,
+    prop: Prop,
+    value: CopiedValue,
+    insert: bool,
+) -> Result<(), AutomergeError> {
+    match value {
+        CopiedValue::Scalar(s) => match (insert, prop) {
+            (true, Prop::Seq(index)) => tx.insert(obj, index, s),
+            (_, prop) => tx.put(obj, prop, s),
+        }
+        .map_err(AutomergeError::AutomergeError)?,
+        CopiedValue::Map(entries) => {
+            let map_id = create_object(tx, obj, prop, ObjType::Map, insert)
+                .map_err(AutomergeError::AutomergeError)?;
+            for (key, value) in entries {
+                write_copy(tx, &map_id, key.into(), value, false)?;
+            }
+        }
+        CopiedValue::List(values) => {
+            let list_id = create_object(tx, obj, prop, ObjType::List, insert)
+                .map_err(AutomergeError::AutomergeError)?;
+            for (index, value) in values.into_iter().enumerate() {
+                write_copy(tx, &list_id, index.into(), value, true)?;
+            }
+        }
+        CopiedValue::Text(text) => {
+            let text_id = create_object(tx, obj, prop, ObjType::Text, insert)
+                .map_err(AutomergeError::AutomergeError)?;
+            tx.splice_text(text_id, 0, 0, &text)
+                .map_err(AutomergeError::AutomergeError)?;
+        }
+    }
+    Ok(())
+}
+
+// inserts values into the list obj starting at index, runs of scalars are inserted with a single splice,
+// on errors path is left pointing to the failing value
+fn insert_values_at(
+    tx: &mut Tx,
+    writing: Option<&TransactionLock>,
+    obj: &ObjId,
+    mut index: usize,
+    values: &PyAny,
+    path: &mut Vec<Prop>,
+) -> Result<(), PyErr> {
+    let mut scalars = Vec::new();
+    for value in values.iter()? {
+        path.push(Prop::Seq(index + scalars.len()));
+        match scalar_value(extract_value(value?)?) {
+            Ok(scalar) => scalars.push(scalar),
+            Err(value) => {
+                // flush the scalars before the nested value, then insert the nested value on its own
+                let inserted = scalars.len();
+                tx.splice(obj, index, 0, scalars.drain(..))
+                    .map_err(AutomergeError::AutomergeError)?;
+                index += inserted;
+                apply_value_at(tx, writing, obj, path, value, true)?;
+                index += 1;
+            }
+        }
+        path.pop();
+    }
+    tx.splice(obj, index, 0, scalars)
+        .map_err(AutomergeError::AutomergeError)?;
+    Ok(())
+}
+
+// extracts a nested value, the error of the derived extraction lists every variant of AutomergeValue,
+// this only keeps the relevant part
+fn extract_value(value: &PyAny) -> PyResult<AutomergeValue<'_>> {
+    value.extract().map_err(|err| {
+        if value.is_instance_of::<PyDateTime>() {
+            match Timestamp::extract(value) {
+                Err(err) => PyTypeError::new_err(err.value(value.py()).to_string()),
+                Ok(_) => err,
+            }
+        } else if value.is_instance_of::<PySet>() || value.is_instance_of::<PyFrozenSet>() {
+            sequence_value(value).err().unwrap_or(err)
+        } else {
+            PyTypeError::new_err(format!(
+                "unsupported value of type {}",
+                value.get_type().name().unwrap_or("?")
+            ))
+        }
+    })
+}
+
+fn obj_type_name(ty: ObjType) -> &'static str {
+    match ty {
+        ObjType::Map => "map",
+        ObjType::Table => "table",
+        ObjType::List => "list",
+        ObjType::Text => "text",
+    }
+}
+
+// the props leading from the root to the object, empty for the root and unreachable objects
+fn obj_path<T: ReadDoc>(doc: &T, obj_id: &ObjId, heads: Option<&[ChangeHash]>) -> Vec<Prop> {
+    match heads {
+        Some(heads) => doc.parents_at(obj_id, heads),
+        None => doc.parents(obj_id),
+    }
+    .map(|parents| parents.path().into_iter().map(|(_, prop)| prop).collect())
+    .unwrap_or_default()
+}
+
+// formats a path like python code accessing it, e.g. items[3].meta
+fn format_path(path: &[Prop]) -> String {
+    let mut formatted = String::new();
+    for prop in path {
+        match prop {
+            Prop::Map(key)
+                if !key.is_empty()
+                    && !key.starts_with(|c: char| c.is_ascii_digit())
+                    && key.chars().all(|c| c.is_alphanumeric() || c == '_') =>
+            {
+                if !formatted.is_empty() {
+                    formatted.push('.');
+                }
+                formatted.push_str(key);
+            }
+            Prop::Map(key) => formatted.push_str(&format!("[{key:?}]")),
+            Prop::Seq(index) => formatted.push_str(&format!("[{index}]")),
+        }
+    }
+    formatted
+}
+
+// Whether the object is still reachable from the root, objects stop being reachable once they
+// (or one of the objects containing them) are deleted or overwritten.
+// Objects which lost a conflict are still reachable with get_all, so they are live as well
+fn is_live<T: ReadDoc>(doc: &T, obj_id: &ObjId, heads: Option<&[ChangeHash]>) -> bool {
+    if *obj_id == automerge::ROOT {
+        return true;
+    }
+    let parents = match heads {
+        Some(heads) => doc.parents_at(obj_id, heads),
+        None => doc.parents(obj_id),
+    };
+    let Ok(parents) = parents else {
+        return false;
+    };
+    let mut child = obj_id.clone();
+    for parent in parents {
+        if !parent.visible
+            && !get_all(doc, parent.obj.clone(), parent.prop, heads)
+                .map_or(false, |values| values.iter().any(|(_, id)| *id == child))
+        {
+            return false;
+        }
+        child = parent.obj;
+    }
+    true
+}
+
+// raises StaleObjectError for proxies of objects which are no longer part of the document
+fn check_live<T: ReadDoc>(
+    doc: &T,
+    obj_id: &ObjId,
+    heads: Option<&[ChangeHash]>,
+) -> Result<(), AutomergeError> {
+    if is_live(doc, obj_id, heads) {
+        Ok(())
+    } else {
+        Err(AutomergeError::StaleObject)
+    }
+}
+
+// describes where a nested object is for error messages, e.g. " (in items[3].meta)", empty for the root
+fn location<T: ReadDoc>(doc: &T, obj_id: &ObjId, heads: Option<&[ChangeHash]>) -> String {
+    let path = obj_path(doc, obj_id, heads);
+    if path.is_empty() {
+        String::new()
+    } else {
+        format!(" (in {})", format_path(&path))
+    }
+}
+
+// special class for unknown automerge values
+// These are written back unchanged, so documents from newer automerge versions survive an edit
+#[pyclass]
+#[derive(Clone, Debug, PartialEq)]
+struct Unknown {
+    #[pyo3(get)]
+    type_code: u8,
+    bytes: Vec<u8>,
+}
+
+#[pymethods]
+impl Unknown {
+    // the value encoding reserves four bits for the type code, of which 0 to 9 are known types
+    #[new]
+    fn new(type_code: u8, bytes: Vec<u8>) -> PyResult<Self> {
+        if !(10..16).contains(&type_code) {
+            return Err(PyValueError::new_err(format!(
+                "type code {type_code} is not an unknown type code (10 to 15)"
+            )));
+        }
+        Ok(Self { type_code, bytes })
+    }
+
+    #[getter]
+    fn bytes(&self, py: Python<'_>) -> Py<PyBytes> {
+        PyBytes::new(py, &self.bytes).into()
+    }
+
+    fn __repr__(&self) -> String {
+        let bytes: String = self.bytes.iter().map(|b| format!("{b:02x}")).collect();
+        format!("Unknown(type_code={}, bytes=0x{})", self.type_code, bytes)
+    }
+
+    fn __eq__(&self, other: &PyAny) -> bool {
+        match other.extract::<PyRef<'_, Unknown>>() {
+            Ok(other) => *self == *other,
+            Err(_) => false,
+        }
+    }
+}
+
+impl From<Unknown> for ScalarValue {
+    fn from(unknown: Unknown) -> ScalarValue {
+        ScalarValue::Unknown {
+            type_code: unknown.type_code,
+            bytes: unknown.bytes,
+        }
+    }
+}
+
+// special class for the automerge Text value which is basically a List that only supports unicode codepoints as values
+#[pyclass]
+#[derive(Debug)]
+struct Text {
+    text: String,
+}
+
+#[pymethods]
+impl Text {
+    #[new]
+    fn new(text: String) -> Self {
+        Self { text }
+    }
+
+    fn __str__(&self) -> String {
+        self.text.clone()
+    }
+
+    // text is equal to other text and to str with the same content
+    fn __eq__(&self, other: &PyAny) -> PyResult<bool> {
+        Ok(text_content(other)?.map_or(false, |other| self.text == other))
+    }
+
+    // hashes like the str, as text compares equal to str
+    fn __hash__(&self, py: Python<'_>) -> PyResult<isize> {
+        self.text.clone().into_py(py).as_ref(py).hash()
+    }
+
+    fn __len__(&self) -> usize {
+        self.text.chars().count()
+    }
+
+    // single characters or slices (with python semantics) of the text, as str
+    fn __getitem__(&self, index_or_slice: SliceOrIndex<'_>) -> PyResult<String> {
+        match index_or_slice {
+            SliceOrIndex::Index(index) => {
+                let index = text_index(index, self.text.chars().count())?;
+                Ok(self.text.chars().nth(index).unwrap().to_string())
+            }
+            SliceOrIndex::Slice(slice) => text_slice(&self.text, slice),
+        }
+    }
+}
+
+// a stable position in a text or list, created by the cursor methods of their proxies
+// cursors can be stored as str or bytes and recreated with Cursor(str_or_bytes)
+#[pyclass]
+#[derive(Clone, Debug)]
+struct Cursor(automerge::Cursor);
+
+#[pymethods]
+impl Cursor {
+    #[new]
+    fn new(value: &PyAny) -> PyResult<Self> {
+        let cursor = if let Ok(value) = value.extract::<&str>() {
+            automerge::Cursor::try_from(value)
+        } else {
+            automerge::Cursor::try_from(value.extract::<PyBytesNT>()?.0)
+        };
+        cursor
+            .map(Cursor)
+            .map_err(|_| PyValueError::new_err(format!("invalid cursor {value}")))
+    }
+
+    fn __str__(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Cursor('{}')", self.0)
+    }
+
+    fn __bytes__<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, &self.0.to_bytes())
+    }
+
+    fn __eq__(&self, other: &PyAny) -> bool {
+        other
+            .extract::<PyRef<'_, Cursor>>()
+            .map_or(false, |other| self.0 == other.0)
+    }
+
+    fn __hash__(&self, py: Python<'_>) -> PyResult<isize> {
+        PyBytes::new(py, &self.0.to_bytes()).hash()
+    }
+}
+
+// resolves the (possibly negative) bounds of a mark, which must not be reversed
+fn mark_range(start: isize, end: isize, length: usize) -> PyResult<(usize, usize)> {
+    let start = text_insert_index(start, length)?;
+    let end = text_insert_index(end, length)?;
+    if start > end {
+        return Err(PyValueError::new_err(format!(
+            "mark starts at {start} after its end {end}"
+        )));
+    }
+    Ok((start, end))
+}
+
+// writes attrs into the map of a block marker
+fn write_block_attrs(
+    tx: &mut Tx,
+    writing: &TransactionLock,
+    block: &ObjId,
+    attrs: Option<&PyMapping>,
+) -> PyResult<()> {
+    if let Some(attrs) = attrs {
+        for item in attrs.items()?.iter()? {
+            let (key, value): (&str, AutomergeValue) = item?.extract()?;
+            apply_value(tx, Some(writing), block, key, value)?;
+        }
+    }
+    Ok(())
+}
+
+fn text_spans<T: ReadDoc>(
+    py: Python<'_>,
+    doc: &T,
+    obj: &ObjId,
+    heads: Option<&[ChangeHash]>,
+) -> PyResult<Vec<PyObject>> {
+    let spans = match heads {
+        Some(heads) => doc.spans_at(obj, heads),
+        None => doc.spans(obj),
+    }
+    .map_err(AutomergeError::AutomergeError)?;
+    spans
+        .map(|span| {
+            Ok(match span {
+                Span::Text(text, marks) => {
+                    let dict = PyDict::new(py);
+                    for (name, value) in marks.iter().flat_map(|marks| marks.iter()) {
+                        dict.set_item(name, scalar_to_py(py, value))?;
+                    }
+                    TextSpan {
+                        text,
+                        marks: dict.into(),
+                    }
+                    .into_py(py)
+                }
+                Span::Block(attrs) => BlockMarker {
+                    attrs: hydrated_map(py, &attrs)?,
+                }
+                .into_py(py),
+            })
+        })
+        .collect()
+}
+
+// converts a value read with hydrate to plain python values, text becomes str
+fn hydrated_to_py(py: Python<'_>, value: &hydrate::Value) -> PyResult<PyObject> {
+    Ok(match value {
+        hydrate::Value::Scalar(s) => scalar_to_py(py, s),
+        hydrate::Value::Map(map) => hydrated_map(py, map)?.into_py(py),
+        hydrate::Value::List(list) => PyList::new(
+            py,
+            list.iter()
+                .map(|item| hydrated_to_py(py, &item.value))
+                .collect::<PyResult<Vec<_>>>()?,
+        )
+        .into_py(py),
+        hydrate::Value::Text(text) => String::from(text).into_py(py),
+    })
+}
+
+fn hydrated_map(py: Python<'_>, map: &hydrate::Map) -> PyResult<Py<PyDict>> {
+    let dict = PyDict::new(py);
+    for (key, item) in map.iter() {
+        dict.set_item(key, hydrated_to_py(py, &item.value)?)?;
+    }
+    Ok(dict.into())
+}
+
+// like text_index, but the end of the text is a valid position to insert at
+fn text_insert_index(index: isize, length: usize) -> PyResult<usize> {
+    sequence_insert_index(index, length, "text")
+}
+
+// like sequence_index, but the end of the list or text is a valid position to insert at
+fn sequence_insert_index(index: isize, length: usize, kind: &str) -> PyResult<usize> {
+    if index == length as isize {
+        Ok(length)
+    } else {
+        sequence_index(index, length, kind)
+    }
+}
+
+// resolves a (possibly negative) index into a text of the given length, counted in characters like automerge does
+fn text_index(index: isize, length: usize) -> PyResult<usize> {
+    sequence_index(index, length, "text")
+}
+
+// resolves a (possibly negative) index into the list or text (kind) of the given length
+fn sequence_index(index: isize, length: usize, kind: &str) -> PyResult<usize> {
+    let position = if index < 0 {
+        index + length as isize
+    } else {
+        index
+    };
+    if position < 0 || position as usize >= length {
+        return Err(PyIndexError::new_err(format!(
+            "index {index} out of range for {kind} of length {length}"
+        )));
+    }
+    Ok(position as usize)
+}
+
+// the characters of text selected by slice, with the semantics of slicing a str
+fn text_slice(text: &str, slice: &PySlice) -> PyResult<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let slice = slice.indices(chars.len() as _)?;
+    Ok((0..slice.slicelength)
+        .map(|i| chars[(slice.start + i * slice.step) as usize])
+        .collect())
+}
+
+// the content of Text, TextView or str values, None for other values
+fn text_content(value: &PyAny) -> PyResult<Option<String>> {
+    Ok(if let Ok(text) = value.extract::<PyRef<'_, Text>>() {
+        Some(text.text.clone())
+    } else if let Ok(text) = value.extract::<PyRef<'_, TextView>>() {
+        Some(text.text()?)
+    } else if let Ok(text) = value.extract::<&str>() {
+        Some(text.to_owned())
+    } else {
+        None
+    })
+}
+
+// A text inside a document, reads always see the current content of the text
+// (or the content at the heads of a historical view)
+#[pyclass]
+pub struct TextView {
+    document: Document,
+}
+
+impl TextView {
+    fn text(&self) -> PyResult<String> {
+        let document = &self.document;
+        Ok(with_doc!(document, |doc| {
+            match &document.heads {
+                Some(heads) => doc.text_at(&document.obj_id, heads),
+                None => doc.text(&document.obj_id),
+            }
+            .map_err(AutomergeError::AutomergeError)?
+        }))
+    }
+}
+
+#[pymethods]
+impl TextView {
+    // a stable reference to the character at index, which keeps pointing to the same
+    // character when text is inserted or deleted before it (see cursor_position)
+    fn cursor(&self, index: isize) -> PyResult<Cursor> {
+        let document = &self.document;
+        with_doc!(document, |doc| {
+            cursor(doc, &document.obj_id, index, document.heads.as_deref())
+        })
+    }
+
+    // the current index of the character the cursor points to,
+    // in the state of the text at heads if given
+    #[pyo3(signature = (cursor, heads=None))]
+    fn cursor_position(&self, cursor: &Cursor, heads: Option<Vec<HashArg<'_>>>) -> PyResult<usize> {
+        let heads = heads.map(extract_heads).transpose()?;
+        let document = &self.document;
+        with_doc!(document, |doc| {
+            let heads = heads.as_deref().or(document.heads.as_deref());
+            cursor_position(doc, &document.obj_id, cursor, heads)
+        })
+    }
+
+    // the marks on the text as (start, end, name, value) tuples
+    fn marks(&self, py: Python<'_>) -> PyResult<Vec<(usize, usize, String, PyObject)>> {
+        let document = &self.document;
+        Ok(with_doc!(document, |doc| {
+            let marks = match &document.heads {
+                Some(heads) => doc.marks_at(&document.obj_id, heads),
+                None => doc.marks(&document.obj_id),
+            }
+            .map_err(AutomergeError::AutomergeError)?;
+            marks
+                .iter()
+                .map(|mark| {
+                    (
+                        mark.start,
+                        mark.end,
+                        mark.name().to_owned(),
+                        scalar_to_py(py, mark.value()),
+                    )
+                })
+                .collect()
+        }))
+    }
+
+    // the text split into TextSpans (runs of text with the same marks) and the BlockMarkers between them
+    fn spans(&self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        let document = &self.document;
+        document.ensure_live()?;
+        with_doc!(document, |doc| {
+            text_spans(py, doc, &document.obj_id, document.heads.as_deref())
+        })
+    }
+
+    fn __str__(&self) -> PyResult<String> {
+        self.document.ensure_live()?;
+        self.text()
+    }
+
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        Ok(format!(
+            "TextView({})",
+            self.text()?.into_py(py).as_ref(py).repr()?
+        ))
+    }
+
+    fn __len__(&self) -> PyResult<usize> {
+        let document = &self.document;
+        document.ensure_live()?;
+        Ok(with_doc!(document, |doc| {
+            length(doc, document.obj_id.clone(), document.heads.as_deref())
+        }))
+    }
+
+    // single characters or slices (with python semantics) of the text, as str
+    fn __getitem__(&self, index_or_slice: SliceOrIndex<'_>) -> PyResult<String> {
+        let document = &self.document;
+        document.ensure_live()?;
+        with_doc!(document, |doc| {
+            let length = length(doc, document.obj_id.clone(), document.heads.as_deref());
+            match index_or_slice {
+                SliceOrIndex::Index(index) => {
+                    let index = text_index(index, length)?;
+                    match get(
+                        doc,
+                        document.obj_id.clone(),
+                        index,
+                        document.heads.as_deref(),
+                    )? {
+                        Some((Value::Scalar(s), _)) => {
+                            Ok(s.to_str().unwrap_or_default().to_owned())
+                        }
+                        _ => Ok(String::new()),
+                    }
+                }
+                SliceOrIndex::Slice(slice) => {
+                    let text = match &document.heads {
+                        Some(heads) => doc.text_at(&document.obj_id, heads),
+                        None => doc.text(&document.obj_id),
+                    }
+                    .map_err(AutomergeError::AutomergeError)?;
+                    text_slice(&text, slice)
+                }
+            }
+        })
+    }
+
+    // text is equal to other text and to str with the same content
+    fn __eq__(&self, other: &PyAny) -> PyResult<bool> {
+        Ok(match text_content(other)? {
+            Some(other) => self.text()? == other,
+            None => false,
+        })
+    }
+
+    // the content can change, so a TextView can't be hashed (use str(text) instead)
+    fn __hash__(&self) -> PyResult<isize> {
+        Err(PyTypeError::new_err("unhashable type: 'TextView'"))
+    }
+
+    fn __contains__(&self, needle: &PyAny) -> PyResult<bool> {
+        match text_content(needle)? {
+            Some(needle) => Ok(self.text()?.contains(&needle)),
+            None => Err(PyTypeError::new_err(format!(
+                "'in <TextView>' requires string as left operand, not {}",
+                needle.get_type().name()?
+            ))),
+        }
+    }
+
+    // these work like the str methods of the same name
+    #[pyo3(signature = (*args))]
+    fn startswith(&self, py: Python<'_>, args: &PyTuple) -> PyResult<PyObject> {
+        self.text()?
+            .into_py(py)
+            .call_method1(py, "startswith", args)
+    }
+
+    #[pyo3(signature = (*args))]
+    fn endswith(&self, py: Python<'_>, args: &PyTuple) -> PyResult<PyObject> {
+        self.text()?.into_py(py).call_method1(py, "endswith", args)
+    }
+
+    #[pyo3(signature = (*args))]
+    fn find(&self, py: Python<'_>, args: &PyTuple) -> PyResult<PyObject> {
+        self.text()?.into_py(py).call_method1(py, "find", args)
+    }
+
+    #[getter]
+    fn obj_id(&self) -> String {
+        self.document.obj_id()
+    }
+
+    #[getter]
+    fn object_type(&self) -> &'static str {
+        "text"
+    }
+
+    fn path(&self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        self.document.path(py)
+    }
+
+    fn is_live(&self) -> PyResult<bool> {
+        self.document.is_live()
+    }
+}
+
+// text written to a document as a Text (a TextView is copied as a ProxyValue)
+#[derive(Debug)]
+struct TextValue(String);
+
+impl<'a> FromPyObject<'a> for TextValue {
+    fn extract(obj: &'a PyAny) -> PyResult<Self> {
+        Ok(TextValue(obj.extract::<PyRef<'_, Text>>()?.text.clone()))
+    }
+}
+
+// the content of an object of a document, see copy_object
+#[derive(Debug)]
+enum CopiedValue {
+    Scalar(ScalarValue),
+    Map(Vec<(String, CopiedValue)>),
+    List(Vec<CopiedValue>),
+    Text(String),
+}
+
+// reads value (with all objects nested in it) so it can be written to another place, counters stay counters
+fn copy_object<T: ReadDoc>(
+    doc: &T,
+    value: Value<'_>,
+    obj_id: ObjId,
+    heads: Option<&[ChangeHash]>,
+) -> Result<CopiedValue, AutomergeError> {
+    Ok(match value {
+        Value::Object(ObjType::Map | ObjType::Table) => CopiedValue::Map(
+            match heads {
+                Some(heads) => doc.map_range_at(obj_id, .., heads),
+                None => doc.map_range(obj_id, ..),
+            }
+            .map(|item| {
+                let key = item.key.to_string();
+                Ok((key, copy_object(doc, item.value, item.id, heads)?))
+            })
+            .collect::<Result<_, AutomergeError>>()?,
+        ),
+        Value::Object(ObjType::List) => CopiedValue::List(
+            match heads {
+                Some(heads) => doc.list_range_at(obj_id, .., heads),
+                None => doc.list_range(obj_id, ..),
+            }
+            .map(|item| copy_object(doc, item.value, item.id, heads))
+            .collect::<Result<_, _>>()?,
+        ),
+        Value::Object(ObjType::Text) => CopiedValue::Text(
+            match heads {
+                Some(heads) => doc.text_at(obj_id, heads),
+                None => doc.text(obj_id),
+            }
+            .map_err(AutomergeError::AutomergeError)?,
+        ),
+        Value::Scalar(s) => CopiedValue::Scalar(s.into_owned()),
+    })
+}
+
+// reads obj_id (with all objects nested in it) like copy_object, raising StaleObjectError if it was deleted
+fn copy_live_object<T: ReadDoc>(
+    doc: &T,
+    obj_id: &ObjId,
+    heads: Option<&[ChangeHash]>,
+) -> Result<CopiedValue, AutomergeError> {
+    check_live(doc, obj_id, heads)?;
+    let ty = doc
+        .object_type(obj_id)
+        .map_err(AutomergeError::AutomergeError)?;
+    copy_object(doc, Value::Object(ty), obj_id.clone(), heads)
+}
+
+// a Mapping, Sequence or text proxy (of any document or transaction) written as a value
+#[derive(Debug)]
+enum ProxyValue {
+    // the content of the proxied object of a document, read under the lock of the document
+    Copied(CopiedValue),
+    // an object of a transaction, read when writing, where it is known whether this is the
+    // transaction being written to (which is already locked)
+    Transaction(DocumentTransaction),
+    // reading the proxied object failed, raised when writing it so the error isn't lost among the
+    // errors of the other variants of AutomergeValue
+    Failed(PyErr),
+}
+
+impl<'a> FromPyObject<'a> for ProxyValue {
+    fn extract(obj: &'a PyAny) -> PyResult<Self> {
+        if !(obj.is_instance_of::<TextView>()
+            || obj.is_instance_of::<Document>()
+            || obj.is_instance_of::<DocumentTransaction>())
+        {
+            return Err(PyTypeError::new_err(format!(
+                "{} is not a proxy of a document",
+                obj.get_type().name()?
+            )));
+        }
+        Ok(ProxyValue::read(obj).unwrap_or_else(ProxyValue::Failed))
+    }
+}
+
+impl ProxyValue {
+    fn read(obj: &PyAny) -> PyResult<Self> {
+        let document = if let Ok(view) = obj.extract::<PyRef<'_, TextView>>() {
+            view.document.clone()
+        } else if let Ok(document) = obj.extract::<PyRef<'_, Document>>() {
+            document.clone()
+        } else {
+            return Ok(ProxyValue::Transaction(
+                obj.extract::<PyRef<'_, DocumentTransaction>>()?.clone(),
+            ));
+        };
+        // never waits for an open transaction, it might be the one this value is written to
+        let state = document
+            .automerge
+            .read()
+            .map_err(|_| AutomergeError::PoisonedDocument)?;
+        let doc = state
+            .doc
+            .as_ref()
+            .or(state.snapshot.as_ref())
+            .ok_or(AutomergeError::UsingDocDuringTransaction)?;
+        Ok(ProxyValue::Copied(copy_live_object(
+            doc,
+            &document.obj_id,
+            document.heads.as_deref(),
+        )?))
+    }
+}
+
+// converts scalars, other values (sequences, mappings, text and too large ints) are returned as they are
+fn scalar_value(value: AutomergeValue) -> Result<ScalarValue, AutomergeValue> {
+    match value {
+        AutomergeValue::BigInt(_)
+        | AutomergeValue::Proxy(_)
+        | AutomergeValue::Sequence(_)
+        | AutomergeValue::Mapping(_)
+        | AutomergeValue::Text(_) => Err(value),
+        value => {
+            let scalar: ScalarValue;
+            match_value!(value,
+                Scalar(s) => { scalar = s.into() },
+                Sequence(_s) => { unreachable!() },
+                Mapping(_m) => { unreachable!() },
+                Text(_t) => { unreachable!() },
+            );
+            Ok(scalar)
+        }
+    }
+}
+
+// the value of a mark, which automerge only supports for scalars
+struct MarkValue(ScalarValue);
+
+impl<'a> FromPyObject<'a> for MarkValue {
+    fn extract(obj: &'a PyAny) -> PyResult<Self> {
+        let kind = match scalar_value(obj.extract::<AutomergeValue>()?) {
+            Ok(scalar) => return Ok(MarkValue(scalar)),
+            Err(AutomergeValue::BigInt(int)) => {
+                return Err(PyOverflowError::new_err(format!(
+                    "int {int} does not fit into a 64 bit integer"
+                )))
+            }
+            Err(AutomergeValue::Sequence(_)) => "a sequence",
+            Err(AutomergeValue::Mapping(_)) => "a mapping",
+            Err(_) => "text",
+        };
+        Err(PyTypeError::new_err(format!(
+            "mark values must be scalars, not {kind}"
+        )))
+    }
+}
+
+// how a mark grows when text is inserted at its edges
+fn expand_mark(expand: &str) -> PyResult<ExpandMark> {
+    match expand {
+        "grow" => Ok(ExpandMark::Both),
+        "before" => Ok(ExpandMark::Before),
+        "after" => Ok(ExpandMark::After),
+        "none" => Ok(ExpandMark::None),
+        expand => Err(PyValueError::new_err(format!(
+            "unknown expand policy {expand:?}, expected \"grow\", \"before\", \"after\" or \"none\""
+        ))),
+    }
+}
+
+// special class for automerge Counters, which support incremeting
+#[pyclass(subclass)]
+#[derive(Clone, Debug)]
+struct Counter(i64);
+
+#[pymethods]
+impl Counter {
+    #[new]
+    fn new(value: i64) -> Self {
+        Self(value)
+    }
+
+    fn get(&self) -> i64 {
+        self.0
+    }
+
+    fn __int__(&self) -> i64 {
+        self.0
+    }
+
+    fn __index__(&self) -> i64 {
+        self.0
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Counter({})", self.0)
+    }
+
+    fn __str__(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn __format__(&self, py: Python<'_>, spec: &str) -> PyResult<PyObject> {
+        self.0.into_py(py).call_method1(py, "__format__", (spec,))
+    }
+
+    // hashes like the plain int, as counters compare equal to ints
+    fn __hash__(&self, py: Python<'_>) -> PyResult<isize> {
+        self.0.into_py(py).as_ref(py).hash()
+    }
+
+    fn __richcmp__(&self, py: Python<'_>, other: &PyAny, op: CompareOp) -> PyObject {
+        let other = match other.extract::<PyRef<'_, Counter>>() {
+            Ok(counter) => counter.0,
+            Err(_) => match other.extract::<i64>() {
+                Ok(int) => int,
+                Err(_) => return py.NotImplemented(),
+            },
+        };
+        op.matches(self.0.cmp(&other)).into_py(py)
+    }
+}
+
+// a counter read outside of a transaction, which remembers where it was read from,
+// so it can be incremented without navigating to it inside a transaction
+#[pyclass(extends=Counter)]
+struct BoundCounter {
+    document: Document,
+    prop: Prop,
+}
+
+impl BoundCounter {
+    fn bind(
+        py: Python<'_>,
+        document: &Document,
+        prop: impl Into<Prop>,
+        value: i64,
+    ) -> PyResult<PyObject> {
+        let init = PyClassInitializer::from(Counter(value)).add_subclass(BoundCounter {
+            document: document.clone(),
+            prop: prop.into(),
+        });
+        Ok(PyCell::new(py, init)?.to_object(py))
+    }
+}
+
+#[pymethods]
+impl BoundCounter {
+    // increments the counter in a transaction of its own
+    fn increment(mut slf: PyRefMut<'_, Self>, increment: i64) -> PyResult<()> {
+        let document = &slf.document;
+        with_doc_mut!(document, |doc| {
+            let mut tx = doc.transaction();
+            tx.increment(document.obj_id.clone(), slf.prop.clone(), increment)
+                .map_err(AutomergeError::AutomergeError)?;
+            tx.commit();
+        });
+        slf.as_mut().0 += increment;
+        Ok(())
+    }
+}
+
+impl From<Counter> for ScalarValue {
+    fn from(counter: Counter) -> ScalarValue {
+        ScalarValue::Counter(counter.0.into())
+    }
+}
+
+// The fork gets a new random actor id unless one is given
+#[pyfunction]
+#[pyo3(signature = (doc, actor=None))]
+pub fn fork(py: Python<'_>, doc: &Document, actor: Option<Actor<'_>>) -> PyResult<PyObject> {
+    let actor = actor.map(ActorId::try_from).transpose()?;
+    let new_doc = with_doc!(doc, |doc| {
+        let mut new_doc = doc.fork();
+        if let Some(actor) = actor {
+            new_doc.set_actor(actor);
+        }
+        new_doc
+    });
+
+    Document::from_loaded_doc(py, new_doc)
+}
+
+// Returns a read only view of the document as it was at the given heads
+// reading at unknown heads would silently ignore them, so reject them upfront
+fn check_heads(doc: &Automerge, heads: &[ChangeHash]) -> Result<(), AutomergeError> {
+    match heads
+        .iter()
+        .find(|hash| doc.get_change_by_hash(hash).is_none())
+    {
+        Some(hash) => Err(AutomergeError::AutomergeError(
+            automerge::AutomergeError::InvalidHash(*hash),
+        )),
+        None => Ok(()),
+    }
+}
+
+#[pyfunction]
+pub fn at(py: Python<'_>, doc: &Document, heads: Vec<HashArg<'_>>) -> PyResult<PyObject> {
+    let heads = extract_heads(heads)?;
+    with_doc!(doc, |doc_| {
+        check_heads(doc_, &heads)?;
+        let ty = doc_
+            .object_type(doc.obj_id.clone())
+            .map_err(AutomergeError::AutomergeError)?;
+        Document::for_subfield(
+            py,
+            doc.automerge.clone(),
+            ty,
+            doc.obj_id.clone(),
+            Some(heads),
+        )
+    })
+}
+
+// Forks the document as it was at the given heads
+#[pyfunction]
+#[pyo3(signature = (doc, heads, actor=None))]
+pub fn fork_at(
+    py: Python<'_>,
+    doc: &Document,
+    heads: Vec<HashArg<'_>>,
+    actor: Option<Actor<'_>>,
+) -> PyResult<PyObject> {
+    let heads = extract_heads(heads)?;
+    let actor = actor.map(ActorId::try_from).transpose()?;
+    let mut new_doc = with_doc!(doc, |doc| {
+        doc.fork_at(&heads)
+            .map_err(AutomergeError::AutomergeError)?
+    });
+    if let Some(actor) = actor {
+        new_doc.set_actor(actor);
+    }
+
+    Document::from_loaded_doc(py, new_doc)
+}
+
+// Returns a new document containing the changes of both documents, leaving both untouched
+#[pyfunction]
+pub fn merged(py: Python<'_>, doc_a: Document, doc_b: Document) -> PyResult<PyObject> {
+    let merged = fork(py, &doc_a, None)?;
+    merge(py, merged.extract::<Document>(py)?, doc_b)?;
+    Ok(merged)
+}
+
+// locks two different documents, always in the same order (by address),
+// so threads locking the same two documents (in any argument order) can't deadlock
+fn lock_both<'a, G>(
+    a: &'a AutomergeDocument,
+    b: &'a AutomergeDocument,
+    lock: impl Fn(&'a AutomergeDocument) -> G,
+) -> (G, G) {
+    if Arc::as_ptr(a) < Arc::as_ptr(b) {
+        let a = lock(a);
+        (a, lock(b))
+    } else {
+        let b = lock(b);
+        (lock(a), b)
+    }
+}
+
+// locks two different documents for writing once neither is owned by a transaction,
+// waiting for the transactions (or failing) depending on the read behavior of the documents.
+// Only one document is waited for at a time, keeping the other one locked could block its transaction
+fn lock_both_documents<'a>(
+    a: &'a AutomergeDocument,
+    b: &'a AutomergeDocument,
+) -> PyResult<(
+    RwLockWriteGuard<'a, DocumentState>,
+    RwLockWriteGuard<'a, DocumentState>,
+)> {
+    loop {
+        let (state_a, state_b) =
+            lock_both(a, b, |doc| lock_document(doc, |doc| doc.write(), |_| true));
+        let (state_a, state_b) = (state_a?, state_b?);
+        let busy = match (state_a.doc.is_some(), state_b.doc.is_some()) {
+            (true, true) => return Ok((state_a, state_b)),
+            (false, _) => a,
+            (true, false) => b,
+        };
+        drop((state_a, state_b));
+        drop(lock_document(
+            busy,
+            |doc| doc.write(),
+            |state| state.doc.is_some(),
+        )?);
+    }
+}
+
+// The GIL is released while merging.
+// The documents are taken as copies of the proxies (sharing the document state),
+// so the python objects aren't borrowed while other threads run
+#[pyfunction]
+pub fn merge(py: Python<'_>, doc_a: Document, doc_b: Document) -> PyResult<Vec<Patch>> {
+    if doc_a.heads.is_some() || doc_b.heads.is_some() {
+        Err(AutomergeError::ModifyingHistoricalView)?
+    }
+    // a document already contains all of its own changes
+    if Arc::ptr_eq(&doc_a.automerge, &doc_b.automerge) {
+        return Ok(vec![]);
+    }
+    let mut patch_log =
+        PatchLog::active(TextRepresentation::String(TextEncoding::UnicodeCodePoint));
+    // the patches are made before unlocking, another transaction could change doc_a afterwards
+    let patches = py.allow_threads(|| {
+        let (mut state_a, mut state_b) = lock_both_documents(&doc_a.automerge, &doc_b.automerge)?;
+        let (Some(a), Some(b)) = (state_a.doc.as_mut(), state_b.doc.as_mut()) else {
+            Err(AutomergeError::UsingDocDuringTransaction)?
+        };
+        a.merge_and_log_patches(b, &mut patch_log)
+            .map_err(AutomergeError::AutomergeError)?;
+        PyResult::Ok(a.make_patches(&mut patch_log))
+    })?;
+    patches
+        .into_iter()
+        .map(|patch| Patch::new(py, &doc_a.automerge, patch, None))
+        .collect()
+}
+
+#[pyfunction]
+pub fn save(py: Python<'_>, doc: &Document) -> PyResult<Py<PyBytes>> {
+    let bytes = lock_document(
+        &doc.automerge,
+        |automerge| automerge.write(),
+        |state| state.doc.is_some(),
+    )?
+    .save()?;
+    Ok(PyBytes::new(py, &bytes[..]).into())
+}
+
+// Returns only the changes since the last call to save or save_incremental,
+// or since the document was loaded or forked
+#[pyfunction]
+pub fn save_incremental(py: Python<'_>, doc: &Document) -> PyResult<Py<PyBytes>> {
+    let bytes = lock_document(
+        &doc.automerge,
+        |automerge| automerge.write(),
+        |state| state.doc.is_some(),
+    )?
+    .save_incremental()?;
+    Ok(PyBytes::new(py, &bytes[..]).into())
+}
+
+#[pyfunction]
+pub fn load(py: Python<'_>, bytes: &PyBytes) -> PyResult<PyObject> {
+    let new_doc = Automerge::load(bytes.as_bytes()).map_err(AutomergeError::LoadError)?;
+    Document::from_loaded_doc(py, new_doc)
+}
+
+// Serializes the state of the document (at the given heads) as JSON.
+// Text becomes a string, counters and timestamps (in milliseconds) become numbers,
+// bytes and values of unknown types can't be represented and raise a ValueError
+#[pyfunction]
+#[pyo3(signature = (doc, heads=None))]
+pub fn to_json(doc: &Document, heads: Option<Vec<HashArg<'_>>>) -> PyResult<String> {
+    let doc = match heads {
+        Some(heads) => doc.view_at(heads)?,
+        None => doc.clone(),
+    };
+    with_doc! {doc, |automerge| {
+        let ty = automerge.object_type(&doc.obj_id).map_err(AutomergeError::AutomergeError)?;
+        let json = value_to_json(automerge, Value::Object(ty), doc.obj_id.clone(), doc.heads.as_deref(), "")?;
+        Ok(json.to_string())
+    }}
+}
+
+fn value_to_json<T: ReadDoc>(
+    doc: &T,
+    value: Value<'_>,
+    obj_id: ObjId,
+    heads: Option<&[ChangeHash]>,
+    path: &str,
+) -> PyResult<JsonValue> {
+    Ok(match value {
+        Value::Object(ObjType::Map | ObjType::Table) => JsonValue::Object(
+            match heads {
+                Some(heads) => doc.map_range_at(obj_id, .., heads),
+                None => doc.map_range(obj_id, ..),
+            }
+            .map(|item| {
+                let path = if path.is_empty() {
+                    item.key.to_string()
+                } else {
+                    format!("{}.{}", path, item.key)
+                };
+                let value = value_to_json(doc, item.value, item.id, heads, &path)?;
+                Ok((item.key.to_string(), value))
+            })
+            .collect::<PyResult<_>>()?,
+        ),
+        Value::Object(ObjType::List) => JsonValue::Array(
+            match heads {
+                Some(heads) => doc.list_range_at(obj_id, .., heads),
+                None => doc.list_range(obj_id, ..),
+            }
+            .map(|item| {
+                let path = format!("{}[{}]", path, item.index);
+                value_to_json(doc, item.value, item.id, heads, &path)
+            })
+            .collect::<PyResult<_>>()?,
+        ),
+        Value::Object(ObjType::Text) => JsonValue::String(
+            match heads {
+                Some(heads) => doc.text_at(obj_id, heads),
+                None => doc.text(obj_id),
+            }
+            .map_err(AutomergeError::AutomergeError)?,
+        ),
+        Value::Scalar(s) => match &*s {
+            ScalarValue::Str(s) => JsonValue::from(s.to_string()),
+            ScalarValue::Int(i) | ScalarValue::Timestamp(i) => JsonValue::from(*i),
+            ScalarValue::Uint(u) => JsonValue::from(*u),
+            ScalarValue::F64(f) if f.is_finite() => JsonValue::from(*f),
+            ScalarValue::Counter(counter) => JsonValue::from(i64::from(counter)),
+            ScalarValue::Boolean(b) => JsonValue::from(*b),
+            ScalarValue::Null => JsonValue::Null,
+            s => {
+                return Err(PyValueError::new_err(format!(
+                    "value {} at {} can not be represented as JSON",
+                    s, path
+                )))
+            }
+        },
+    })
+}
+
+// Creates a new document from a JSON object, with all of its content added in one change.
+// JSON strings become plain strings, not Text
+#[pyfunction]
+pub fn load_json(py: Python<'_>, json: &str) -> PyResult<PyObject> {
+    let fields = match serde_json::from_str(json) {
+        Ok(JsonValue::Object(fields)) => fields,
+        Ok(_) => return Err(PyValueError::new_err("JSON document must be an object")),
+        Err(e) => return Err(PyValueError::new_err(format!("invalid JSON: {}", e))),
+    };
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    fill_from_json(&mut tx, &automerge::ROOT, JsonValue::Object(fields))?;
+    tx.commit();
+    Document::from_doc(py, doc)
+}
+
+// returns the object type for arrays and objects, which can't be stored as a scalar
+fn json_to_scalar(value: JsonValue) -> Result<ScalarValue, (ObjType, JsonValue)> {
+    Ok(match value {
+        JsonValue::Null => ScalarValue::Null,
+        JsonValue::Bool(b) => ScalarValue::Boolean(b),
+        JsonValue::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                ScalarValue::Int(i)
+            } else if let Some(u) = n.as_u64() {
+                ScalarValue::Uint(u)
+            } else {
+                ScalarValue::F64(n.as_f64().unwrap_or(f64::NAN))
+            }
+        }
+        JsonValue::String(s) => ScalarValue::Str(s.into()),
+        JsonValue::Array(_) => return Err((ObjType::List, value)),
+        JsonValue::Object(_) => return Err((ObjType::Map, value)),
+    })
+}
+
+// adds the items of a JSON array to an empty list or the fields of a JSON object to an empty map
+fn fill_from_json(tx: &mut Tx, obj: &ObjId, value: JsonValue) -> Result<(), AutomergeError> {
+    match value {
+        JsonValue::Array(items) => {
+            for (i, item) in items.into_iter().enumerate() {
+                match json_to_scalar(item) {
+                    Ok(scalar) => tx
+                        .insert(obj, i, scalar)
+                        .map_err(AutomergeError::AutomergeError)?,
+                    Err((ty, item)) => {
+                        let id = tx
+                            .insert_object(obj, i, ty)
+                            .map_err(AutomergeError::AutomergeError)?;
+                        fill_from_json(tx, &id, item)?;
+                    }
+                }
+            }
+        }
+        JsonValue::Object(fields) => {
+            for (key, value) in fields {
+                match json_to_scalar(value) {
+                    Ok(scalar) => tx
+                        .put(obj, key, scalar)
+                        .map_err(AutomergeError::AutomergeError)?,
+                    Err((ty, value)) => {
+                        let id = tx
+                            .put_object(obj, key, ty)
+                            .map_err(AutomergeError::AutomergeError)?;
+                        fill_from_json(tx, &id, value)?;
+                    }
+                }
+            }
+        }
+        _ => unreachable!("only arrays and objects have content"),
+    }
+    Ok(())
+}
+
+const FILE_CHUNK_SIZE: usize = 1 << 20;
+
+// A path (str or os.PathLike) which is opened natively, or a python file object opened in binary mode
+#[derive(FromPyObject)]
+pub enum PathOrFile<'a> {
+    Path(PathBuf),
+    File(&'a PyAny),
+}
+
+fn io_error(error: std::io::Error, path: &Path) -> PyErr {
+    let path = path.display().to_string();
+    match error.raw_os_error() {
+        // OSError picks the matching subclass (e.g. FileNotFoundError) from the errno
+        Some(errno) => PyOSError::new_err((errno, error.to_string(), path)),
+        None => PyOSError::new_err(format!("{}: {}", path, error)),
+    }
+}
+
+// Saves the document like save, but writes it to a file instead of returning the bytes.
+// File objects are written in chunks, so no second copy of the saved document is created
+#[pyfunction]
+pub fn save_to_file(py: Python<'_>, doc: &Document, target: PathOrFile<'_>) -> PyResult<()> {
+    let bytes = lock_document(
+        &doc.automerge,
+        |automerge| automerge.write(),
+        |state| state.doc.is_some(),
+    )?
+    .save()?;
+    match target {
+        PathOrFile::Path(path) => py
+            .allow_threads(|| std::fs::write(&path, &bytes))
+            .map_err(|e| io_error(e, &path)),
+        PathOrFile::File(file) => {
+            for chunk in bytes.chunks(FILE_CHUNK_SIZE) {
+                file.call_method1("write", (PyBytes::new(py, chunk),))?;
+            }
+            Ok(())
+        }
+    }
+}
+
+// Loads a document saved with save or save_to_file from a file
+#[pyfunction]
+pub fn load_from_file(py: Python<'_>, source: PathOrFile<'_>) -> PyResult<PyObject> {
+    let bytes = match source {
+        PathOrFile::Path(path) => py
+            .allow_threads(|| std::fs::read(&path))
+            .map_err(|e| io_error(e, &path))?,
+        PathOrFile::File(file) => {
+            let mut bytes = Vec::new();
+            loop {
+                let chunk = file.call_method1("read", (FILE_CHUNK_SIZE,))?;
+                let chunk: &PyBytes = chunk
+                    .downcast()
+                    .map_err(|_| PyTypeError::new_err("file must be opened in binary mode"))?;
+                if chunk.as_bytes().is_empty() {
+                    break;
+                }
+                bytes.extend_from_slice(chunk.as_bytes());
+            }
+            bytes
+        }
+    };
+    let new_doc = Automerge::load(&bytes).map_err(AutomergeError::LoadError)?;
+    Document::from_loaded_doc(py, new_doc)
+}
+
+// Automerge silently skips chunks it can not parse when loading into a non empty document.
+// To reject corrupt input before anything is applied, we split the input into its chunks
+// (magic bytes, checksum, chunk type, leb128 length, data) and check each of them upfront.
+// Changes are small and parsed completely, documents are only checked against their checksum,
+// as parsing them here would load the whole document twice.
+// This mirrors the binary format of automerge 0.6 (see storage/chunk.rs there): chunk type 0 is a document,
+// 1 a change and 2 a compressed change, the checksum is the start of the sha256 hash of type, length and data.
+// It has to be checked again when upgrading automerge
+fn validate_chunks(mut data: &[u8]) -> Result<(), AutomergeError> {
+    const MAGIC_BYTES: [u8; 4] = [0x85, 0x6f, 0x4a, 0x83];
+    const HEADER_LEN: usize = 9;
+    while !data.is_empty() {
+        if data.len() < HEADER_LEN || data[..4] != MAGIC_BYTES {
+            return Err(AutomergeError::InvalidChunk(
+                "invalid chunk header".to_string(),
+            ));
+        }
+        let mut data_len: usize = 0;
+        let mut chunk_start = HEADER_LEN;
+        loop {
+            let byte = *data.get(chunk_start).ok_or_else(|| {
+                AutomergeError::InvalidChunk("truncated chunk header".to_string())
+            })?;
+            let shift = 7 * (chunk_start - HEADER_LEN);
+            if shift >= usize::BITS as usize {
+                return Err(AutomergeError::InvalidChunk(
+                    "invalid chunk length".to_string(),
+                ));
+            }
+            data_len |= usize::from(byte & 0x7f) << shift;
+            chunk_start += 1;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+        let chunk_len = chunk_start
+            .checked_add(data_len)
+            .filter(|len| *len <= data.len())
+            .ok_or_else(|| AutomergeError::InvalidChunk("truncated chunk".to_string()))?;
+        let (chunk, rest) = data.split_at(chunk_len);
+        match chunk[8] {
+            0 => {
+                if chunk[4..8] != chunk_checksum(&chunk[8..]) {
+                    return Err(AutomergeError::InvalidChunk(
+                        "invalid document checksum".to_string(),
+                    ));
+                }
+            }
+            1 | 2 => {
+                automerge::Change::from_bytes(chunk.to_vec())
+                    .map_err(AutomergeError::LoadChangeError)?;
+            }
+            ty => {
+                return Err(AutomergeError::InvalidChunk(format!(
+                    "unknown chunk type {}",
+                    ty
+                )))
+            }
+        }
+        data = rest;
+    }
+    Ok(())
+}
+
+// the first bytes of the sha256 hash of the chunk after its checksum (chunk type, length and data),
+// hashing the bytes as they are in the input
+fn chunk_checksum(chunk: &[u8]) -> [u8; 4] {
+    let hash = Sha256::digest(chunk);
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+// Applies an incremental save (or a full save) to an existing document
+// Returns the number of ops which were applied
+#[pyfunction]
+pub fn load_incremental(doc: &mut Document, bytes: &PyBytes) -> PyResult<usize> {
+    validate_chunks(bytes.as_bytes())?;
+    Ok(with_doc_mut!(doc, |doc| {
+        doc.load_incremental(bytes.as_bytes())
+            .map_err(AutomergeError::LoadError)?
+    }))
+}
+
+#[pyclass]
+#[derive(Clone)]
+pub struct Change {
+    change: automerge::Change,
+}
+
+#[pymethods]
+impl Change {
+    #[new]
+    fn new(bytes: &PyBytes) -> PyResult<Self> {
+        Ok(Self {
+            change: automerge::Change::from_bytes(bytes.as_bytes().to_vec())
+                .map_err(AutomergeError::LoadChangeError)?,
+        })
+    }
+
+    fn bytes(&mut self, py: Python<'_>) -> Py<PyBytes> {
+        PyBytes::new(py, &*self.change.bytes()).into()
+    }
+
+    fn decode(&mut self, py: Python<'_>) -> PyResult<ExpandedChange> {
+        Ok(ExpandedChange {
+            change: self.change.decode(),
+        })
+    }
+
+    // the decoded change as JSON, see ExpandedChange.to_json
+    fn to_json(&self) -> PyResult<String> {
+        Ok(expanded_change_to_json(&self.change.decode())?.to_string())
+    }
+
+    #[getter]
+    fn hash(&self) -> PyChangeHash {
+        PyChangeHash(self.change.hash())
+    }
+
+    #[getter]
+    fn actor_id(&self, py: Python<'_>) -> Py<PyBytes> {
+        PyBytes::new(py, self.change.actor_id().to_bytes()).into()
+    }
+
+    #[getter]
+    fn seq(&self) -> u64 {
+        self.change.seq()
+    }
+
+    #[getter]
+    fn deps(&self) -> Vec<PyChangeHash> {
+        self.change
+            .deps()
+            .iter()
+            .copied()
+            .map(PyChangeHash)
+            .collect()
+    }
+
+    #[getter]
+    fn message(&self) -> Option<String> {
+        self.change.message().cloned()
+    }
+
+    // milliseconds since the unix epoch, as recorded by the author
+    #[getter]
+    fn timestamp(&self) -> i64 {
+        self.change.timestamp()
+    }
+
+    // the number of operations in the change
+    #[getter]
+    fn len(&self) -> usize {
+        self.change.len()
+    }
+
+    fn __eq__(&self, other: &PyAny) -> bool {
+        match other.extract::<PyRef<'_, Change>>() {
+            Ok(other) => self.change.hash() == other.change.hash(),
+            Err(_) => false,
+        }
+    }
+
+    fn __hash__(&self) -> isize {
+        let hash = self.change.hash();
+        isize::from_le_bytes(
+            hash.as_ref()[..std::mem::size_of::<isize>()]
+                .try_into()
+                .unwrap(),
+        )
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Change(actor={}, seq={}, message={})",
+            self.change.actor_id(),
+            self.change.seq(),
+            match self.change.message() {
+                Some(message) => format!("{message:?}"),
+                None => "None".to_string(),
+            }
+        )
+    }
+}
+
+#[pyclass]
+#[derive(Debug, Clone)]
+pub struct ExpandedChange {
+    change: automerge::ExpandedChange,
+}
+
+#[pymethods]
+impl ExpandedChange {
+    #[getter]
+    fn hash(&self) -> Option<PyChangeHash> {
+        self.change.hash.map(PyChangeHash)
+    }
+
+    #[getter]
+    fn actor_id(&self, py: Python<'_>) -> Py<PyBytes> {
+        PyBytes::new(py, self.change.actor_id.to_bytes()).into()
+    }
+
+    #[getter]
+    fn seq(&self) -> u64 {
+        self.change.seq
+    }
+
+    #[getter]
+    fn start_op(&self) -> u64 {
+        self.change.start_op.get()
+    }
+
+    // milliseconds since the unix epoch, as recorded by the author
+    #[getter]
+    fn time(&self) -> i64 {
+        self.change.time
+    }
+
+    #[getter]
+    fn message(&self) -> Option<String> {
+        self.change.message.clone()
+    }
+
+    #[getter]
+    fn deps(&self) -> Vec<PyChangeHash> {
+        self.change.deps.iter().copied().map(PyChangeHash).collect()
+    }
+
+    #[getter]
+    fn extra_bytes(&self, py: Python<'_>) -> Py<PyBytes> {
+        PyBytes::new(py, &self.change.extra_bytes).into()
+    }
+
+    // the operations of the change as a list of dicts with the keys
+    // action: one of put, delete, increment, make_map, make_table, make_list, make_text, mark_begin or mark_end
+    // object: the id of the object the operation applies to ("_root" or "counter@actor")
+    // key (for maps) or elem_id (for sequences, "_head" or "counter@actor")
+    // insert: whether the operation inserts a new element into a sequence
+    // value: the value for put, increment and mark_begin operations
+    // pred: the ids of the operations this operation overwrites
+    #[getter]
+    fn operations(&self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        op_summaries(&self.change)?
+            .into_iter()
+            .map(|op| {
+                let dict = PyDict::new(py);
+                dict.set_item("action", op.action)?;
+                dict.set_item("object", op.object)?;
+                match op.key {
+                    OpKey::Map(key) => dict.set_item("key", key)?,
+                    OpKey::Elem(elem_id) => dict.set_item("elem_id", elem_id)?,
+                }
+                dict.set_item("insert", op.insert)?;
+                if let Some(value) = op.value {
+                    dict.set_item("value", scalar_to_py(py, &value))?;
+                }
+                dict.set_item("pred", op.pred)?;
+                Ok(dict.into_py(py))
+            })
+            .collect()
+    }
+
+    // the change as a JSON object with the same fields as the getters,
+    // actor ids and hashes are hex encoded and bytes are base64 encoded
+    fn to_json(&self) -> PyResult<String> {
+        Ok(expanded_change_to_json(&self.change)?.to_string())
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+struct OpSummary {
+    action: &'static str,
+    object: String,
+    key: OpKey,
+    insert: bool,
+    value: Option<ScalarValue>,
+    pred: Vec<String>,
+}
+
+enum OpKey {
+    Map(String),
+    Elem(String),
+}
+
+// The op types and keys inside an ExpandedChange are not nameable outside of automerge,
+// but they serialize to plain strings
+fn serialized_str<T: serde::Serialize>(value: &T) -> PyResult<String> {
+    match serde_json::to_value(value) {
+        Ok(JsonValue::String(s)) => Ok(s),
+        Ok(other) => Err(PyValueError::new_err(format!(
+            "unexpected op field {}",
+            other
+        ))),
+        Err(e) => Err(PyValueError::new_err(e.to_string())),
+    }
+}
+
+fn op_summaries(change: &automerge::ExpandedChange) -> PyResult<Vec<OpSummary>> {
+    change
+        .operations
+        .iter()
+        .map(|op| {
+            Ok(OpSummary {
+                action: match serialized_str(&op.action)?.as_str() {
+                    "set" => "put",
+                    "del" => "delete",
+                    "inc" => "increment",
+                    "makeMap" => "make_map",
+                    "makeTable" => "make_table",
+                    "makeList" => "make_list",
+                    "makeText" => "make_text",
+                    "markBegin" => "mark_begin",
+                    "markEnd" => "mark_end",
+                    other => {
+                        return Err(PyValueError::new_err(format!(
+                            "unknown op action {}",
+                            other
+                        )))
+                    }
+                },
+                object: op.obj.to_string(),
+                key: match op.key.as_element_id() {
+                    Some(elem_id) => OpKey::Elem(elem_id.to_string()),
+                    None => OpKey::Map(serialized_str(&op.key)?),
+                },
+                insert: op.insert,
+                value: op.primitive_value(),
+                pred: op.pred.iter().map(|id| id.to_string()).collect(),
+            })
+        })
+        .collect()
+}
+
+fn expanded_change_to_json(change: &automerge::ExpandedChange) -> PyResult<JsonValue> {
+    let operations: Vec<JsonValue> = op_summaries(change)?
+        .into_iter()
+        .map(|op| {
+            let mut fields = serde_json::Map::new();
+            fields.insert("action".into(), op.action.into());
+            fields.insert("object".into(), op.object.into());
+            match op.key {
+                OpKey::Map(key) => fields.insert("key".into(), key.into()),
+                OpKey::Elem(elem_id) => fields.insert("elem_id".into(), elem_id.into()),
+            };
+            fields.insert("insert".into(), op.insert.into());
+            if let Some(value) = op.value {
+                fields.extend(scalar_to_json(&value));
+            }
+            fields.insert("pred".into(), op.pred.into());
+            JsonValue::Object(fields)
+        })
+        .collect();
+    Ok(json!({
+        "hash": change.hash.map(|hash| hash.to_string()),
+        "actor_id": change.actor_id.to_string(),
+        "seq": change.seq,
+        "start_op": change.start_op.get(),
+        "time": change.time,
+        "message": change.message,
+        "deps": change.deps.iter().map(|hash| hash.to_string()).collect::<Vec<_>>(),
+        "extra_bytes": BASE64.encode(&change.extra_bytes),
+        "operations": operations,
+    }))
+}
+
+// the value of an op as JSON, types which JSON has no equivalent for get an additional datatype field
+fn scalar_to_json(value: &ScalarValue) -> serde_json::Map<String, JsonValue> {
+    let mut fields = serde_json::Map::new();
+    let (value, datatype) = match value {
+        ScalarValue::Bytes(b) => (BASE64.encode(b).into(), Some("bytes")),
+        ScalarValue::Str(s) => (s.to_string().into(), None),
+        ScalarValue::Int(i) => ((*i).into(), None),
+        ScalarValue::Uint(u) => ((*u).into(), None),
+        ScalarValue::F64(f) => ((*f).into(), None),
+        ScalarValue::Counter(c) => (i64::from(c).into(), Some("counter")),
+        ScalarValue::Timestamp(t) => ((*t).into(), Some("timestamp")),
+        ScalarValue::Boolean(b) => ((*b).into(), None),
+        ScalarValue::Unknown { type_code, bytes } => {
+            fields.insert("type_code".into(), (*type_code).into());
+            (BASE64.encode(bytes).into(), Some("unknown"))
+        }
+        ScalarValue::Null => (JsonValue::Null, None),
+    };
+    fields.insert("value".into(), value);
+    if let Some(datatype) = datatype {
+        fields.insert("datatype".into(), datatype.into());
+    }
+    fields
+}
+
+// A change to the state of a document, as produced by diff and merge or passed to the on_patch callback of apply_changes
+// action is one of put, insert, delete, splice_text, increment, conflict or mark
+// path is the list of keys and indices leading from the root to the changed object
+// and key is the key or index in that object (None for marks)
+#[pyclass(get_all)]
+pub struct Patch {
+    action: &'static str,
+    path: Vec<PyObject>,
+    key: PyObject,
+    // the new value for put, a list of values for insert, the inserted str for splice_text,
+    // the amount for increment and a list of (name, value, start, end) tuples for mark
+    value: PyObject,
+    // the number of deleted elements
+    length: usize,
+}
+
+#[pymethods]
+impl Patch {
+    fn __repr__(&self, py: Python<'_>) -> String {
+        format!(
+            "Patch(action={:?}, path={}, key={}, value={}, length={})",
+            self.action,
+            self.path.to_object(py),
+            self.key,
+            self.value,
+            self.length
+        )
+    }
+}
+
+fn prop_to_py(py: Python<'_>, prop: Prop) -> PyObject {
+    match prop {
+        Prop::Map(key) => key.into_py(py),
+        Prop::Seq(index) => index.into_py(py),
+    }
+}
+
+impl Patch {
+    // converts a automerge patch, nested objects are read at the given heads
+    fn new(
+        py: Python<'_>,
+        automerge: &AutomergeDocument,
+        patch: automerge::Patch,
+        heads: Option<&[ChangeHash]>,
+    ) -> PyResult<Self> {
+        use automerge::PatchAction::*;
+        let value_to_py = |(value, id): (Value<'_>, ObjId)| match value {
+            Value::Object(ty) => Document::for_subfield(
+                py,
+                automerge.clone(),
+                ty,
+                id,
+                heads.map(|heads| heads.to_vec()),
+            ),
+            Value::Scalar(s) => Ok(scalar_to_py(py, &s)),
+        };
+        let (action, key, value, length) = match patch.action {
+            PutMap { key, value, .. } => ("put", key.into_py(py), value_to_py(value)?, 0),
+            PutSeq { index, value, .. } => ("put", index.into_py(py), value_to_py(value)?, 0),
+            Insert { index, values, .. } => {
+                let values = values
+                    .iter()
+                    .map(|(value, id, _)| value_to_py((value.clone(), id.clone())))
+                    .collect::<PyResult<Vec<_>>>()?;
+                ("insert", index.into_py(py), values.into_py(py), 0)
+            }
+            SpliceText { index, value, .. } => (
+                "splice_text",
+                index.into_py(py),
+                value.make_string().into_py(py),
+                0,
+            ),
+            Increment { prop, value } => ("increment", prop_to_py(py, prop), value.into_py(py), 0),
+            Conflict { prop } => ("conflict", prop_to_py(py, prop), py.None(), 0),
+            DeleteMap { key } => ("delete", key.into_py(py), py.None(), 1),
+            DeleteSeq { index, length } => ("delete", index.into_py(py), py.None(), length),
+            Mark { marks } => {
+                let marks: Vec<_> = marks
+                    .iter()
+                    .map(|mark| {
+                        (
+                            mark.name(),
+                            scalar_to_py(py, mark.value()),
+                            mark.start,
+                            mark.end,
+                        )
+                    })
+                    .collect();
+                ("mark", py.None(), marks.into_py(py), 0)
+            }
+        };
+        Ok(Patch {
+            action,
+            path: patch
+                .path
+                .into_iter()
+                .map(|(_, prop)| prop_to_py(py, prop))
+                .collect(),
+            key,
+            value,
+            length,
+        })
+    }
+}
+
+// Returns the patches which turn the document at the before heads into the document at the after heads
+#[pyfunction]
+pub fn diff(
+    py: Python<'_>,
+    doc: &Document,
+    before: Vec<HashArg<'_>>,
+    after: Vec<HashArg<'_>>,
+) -> PyResult<Vec<Patch>> {
+    let before = extract_heads(before)?;
+    let after = extract_heads(after)?;
+    with_doc!(doc, |doc_| {
+        check_heads(doc_, &before)?;
+        check_heads(doc_, &after)?;
+        doc_.diff(
+            &before,
+            &after,
+            TextRepresentation::String(TextEncoding::UnicodeCodePoint),
+        )
+        .into_iter()
+        .map(|patch| Patch::new(py, &doc.automerge, patch, Some(&after)))
+        .collect()
+    })
+}
+
+// Describes the uncommited changes of a transaction as patches,
+// by comparing the objects in the transaction with their state at the start of the transaction.
+// Text is compared by its common prefix and suffix. Overwritten list elements are described as puts
+// and values of new objects are materialized
+fn pending_patches(
+    py: Python<'_>,
+    tx: &Tx<'_>,
+    before: &[ChangeHash],
+    obj_id: ObjId,
+    path: &mut Vec<Prop>,
+    patches: &mut Vec<Patch>,
+) -> PyResult<()> {
+    let mut patch = |action, prop: Prop, value: PyObject, length| {
+        patches.push(Patch {
+            action,
+            path: path
+                .iter()
+                .cloned()
+                .map(|prop| prop_to_py(py, prop))
+                .collect(),
+            key: prop_to_py(py, prop),
+            value,
+            length,
+        })
+    };
+    // objects which already existed before the transaction are compared recursively
+    let mut nested = Vec::new();
+    match tx
+        .object_type(&obj_id)
+        .map_err(AutomergeError::AutomergeError)?
+    {
+        ObjType::Map | ObjType::Table => {
+            let old: HashMap<_, _> = tx
+                .map_range_at(&obj_id, .., before)
+                .map(|item| (item.key, (item.value, item.id)))
+                .collect();
+            let new: Vec<_> = tx.map_range(&obj_id, ..).collect();
+            for key in old.keys() {
+                if !new.iter().any(|item| item.key == *key) {
+                    patch("delete", Prop::Map(key.to_string()), py.None(), 1);
+                }
+            }
+            for item in new {
+                let prop = Prop::Map(item.key.to_string());
+                match (old.get(item.key), &item.value) {
+                    (Some((_, id)), Value::Object(_)) if *id == item.id => {
+                        nested.push((prop, item.id))
+                    }
+                    (Some((Value::Scalar(old_value), id)), Value::Scalar(new_value))
+                        if *id == item.id =>
+                    {
+                        if let (ScalarValue::Counter(old_value), ScalarValue::Counter(new_value)) =
+                            (&**old_value, &**new_value)
+                        {
+                            let by = i64::from(new_value) - i64::from(old_value);
+                            if by != 0 {
+                                patch("increment", prop, by.into_py(py), 0);
+                            }
+                        }
+                    }
+                    _ => patch(
+                        "put",
+                        prop,
+                        materialize(py, tx, item.value, item.id, None)?,
+                        0,
+                    ),
+                }
+            }
+        }
+        ObjType::List => {
+            let old: Vec<_> = tx
+                .list_range_at(&obj_id, .., before)
+                .map(|item| (item.value, item.id))
+                .collect();
+            let new: Vec<_> = tx
+                .list_range(&obj_id, ..)
+                .map(|item| (item.value, item.id))
+                .collect();
+            // elements keep their ids (unless they are overwritten), so the elements
+            // which are in both lists are the unchanged ones and appear in the same order
+            let old_ids: HashSet<_> = old.iter().map(|(_, id)| id).collect();
+            let new_ids: HashSet<_> = new.iter().map(|(_, id)| id).collect();
+            let (mut i, mut j) = (0, 0);
+            while i < old.len() || j < new.len() {
+                if i < old.len() && j < new.len() && old[i].1 == new[j].1 {
+                    match (&old[i].0, &new[j].0) {
+                        (Value::Object(_), Value::Object(_)) => {
+                            nested.push((Prop::Seq(j), new[j].1.clone()))
+                        }
+                        (Value::Scalar(old_value), Value::Scalar(new_value)) => {
+                            if let (
+                                ScalarValue::Counter(old_value),
+                                ScalarValue::Counter(new_value),
+                            ) = (&**old_value, &**new_value)
+                            {
+                                let by = i64::from(new_value) - i64::from(old_value);
+                                if by != 0 {
+                                    patch("increment", Prop::Seq(j), by.into_py(py), 0);
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                    i += 1;
+                    j += 1;
+                    continue;
+                }
+                let removed = old[i..]
+                    .iter()
+                    .take_while(|(_, id)| !new_ids.contains(id))
+                    .count();
+                let added = new[j..]
+                    .iter()
+                    .take_while(|(_, id)| !old_ids.contains(id))
+                    .count();
+                let mut values = new[j..j + added]
+                    .iter()
+                    .map(|(value, id)| materialize(py, tx, value.clone(), id.clone(), None));
+                for index in j..j + removed.min(added) {
+                    patch("put", Prop::Seq(index), values.next().unwrap()?, 0);
+                }
+                let index = j + removed.min(added);
+                if removed > added {
+                    patch("delete", Prop::Seq(index), py.None(), removed - added);
+                } else if removed < added {
+                    let values = values.collect::<PyResult<Vec<_>>>()?;
+                    patch("insert", Prop::Seq(index), values.into_py(py), 0);
+                }
+                i += removed;
+                j += added;
+            }
+        }
+        ObjType::Text => {
+            let old: Vec<char> = tx
+                .text_at(&obj_id, before)
+                .map_err(AutomergeError::AutomergeError)?
+                .chars()
+                .collect();
+            let new: Vec<char> = tx
+                .text(&obj_id)
+                .map_err(AutomergeError::AutomergeError)?
+                .chars()
+                .collect();
+            let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+            let suffix = old[prefix..]
+                .iter()
+                .rev()
+                .zip(new[prefix..].iter().rev())
+                .take_while(|(a, b)| a == b)
+                .count();
+            let removed = old.len() - prefix - suffix;
+            if removed > 0 {
+                patch("delete", Prop::Seq(prefix), py.None(), removed);
+            }
+            if new.len() - suffix > prefix {
+                let inserted: String = new[prefix..new.len() - suffix].iter().collect();
+                patch("splice_text", Prop::Seq(prefix), inserted.into_py(py), 0);
+            }
+        }
+    }
+    for (prop, id) in nested {
+        path.push(prop);
+        pending_patches(py, tx, before, id, path, patches)?;
+        path.pop();
+    }
+    Ok(())
+}
+
+// The result of apply_changes
+// applied counts the changes which were applied, including the ones which were pending from earlier calls
+// pending are the hashes of the changes still waiting for missing dependencies when apply_changes returned
+// (see get_pending_changes and get_missing_deps)
+#[pyclass(get_all)]
+pub struct ApplyResult {
+    applied: usize,
+    pending: Vec<PyChangeHash>,
+}
+
+#[pymethods]
+impl ApplyResult {
+    fn __repr__(&self) -> String {
+        format!(
+            "ApplyResult(applied={}, pending={})",
+            self.applied,
+            self.pending.len()
+        )
+    }
+}
+
+const APPLY_BATCH_SIZE: usize = 1024;
+
+fn extract_change(change: &PyAny) -> PyResult<automerge::Change> {
+    Ok(if let Ok(change) = change.downcast::<PyBytes>() {
+        automerge::Change::from_bytes(change.as_bytes().to_vec())
+            .map_err(AutomergeError::LoadChangeError)?
+    } else {
+        Change::extract(change)?.change
+    })
 }
 
-// TODO(robin): allow arbitrary things and use .__dict__?
-// These are the values we support for conversion into Automerge values
-#[derive(Debug, FromPyObject)]
-enum AutomergeValue<'a> {
-    Boolean(bool),
-    Str(&'a str),
-    Int(i64),
-    Uint(u64),
-    F64(f64),
-    Counter(Counter),
-    Text(&'a PyCell<Text>),
-    Bytes(PyBytesNT<'a>),
-    Mapping(&'a PyMapping),
-    Sequence(&'a PySequence),
-    Null(None),
+// changes can be a single Change or bytes object or any iterable of them.
+// Iterables are consumed in batches, so generators don't need to be materialized;
+// the document is not locked while the iterable is advanced.
+// If a change can't be read or applied, the changes before it stay applied
+// and the raised error says how many changes were applied.
+// If on_patch is given, it is called with every resulting patch once all changes are applied
+#[pyfunction]
+#[pyo3(signature = (doc, changes, on_patch=None))]
+pub fn apply_changes(
+    py: Python<'_>,
+    doc: &Document,
+    changes: &PyAny,
+    on_patch: Option<&PyAny>,
+) -> PyResult<ApplyResult> {
+    if doc.heads.is_some() {
+        Err(AutomergeError::ModifyingHistoricalView)?
+    }
+    let mut patch_log = PatchLog::new(
+        on_patch.is_some(),
+        TextRepresentation::String(TextEncoding::UnicodeCodePoint),
+    );
+    let mut applied = 0;
+    let mut pending = Vec::new();
+    // the changes are extracted from python before, so the GIL can be released while applying them
+    let mut apply_batch = |batch: Vec<automerge::Change>| -> PyResult<()> {
+        let (batch_applied, batch_pending) = py.allow_threads(|| {
+            let mut state = lock_document(
+                &doc.automerge,
+                |automerge| automerge.write(),
+                |state| state.doc.is_some(),
+            )?;
+            PyResult::Ok(state.apply_changes(batch, &mut patch_log))
+        })?;
+        applied += batch_applied;
+        pending = batch_pending?;
+        Ok(())
+    };
+    let result = if changes.is_instance_of::<PyBytes>() || changes.is_instance_of::<Change>() {
+        extract_change(changes).and_then(|change| apply_batch(vec![change]))
+    } else {
+        (|| {
+            let mut batch = Vec::with_capacity(APPLY_BATCH_SIZE);
+            for change in changes.iter()? {
+                match change.and_then(extract_change) {
+                    Ok(change) => batch.push(change),
+                    Err(e) => {
+                        apply_batch(batch)?;
+                        return Err(e);
+                    }
+                }
+                if batch.len() == APPLY_BATCH_SIZE {
+                    apply_batch(std::mem::take(&mut batch))?;
+                }
+            }
+            apply_batch(batch)
+        })()
+    };
+    if let Err(e) = result {
+        let error = PyErr::from_type(
+            e.get_type(py),
+            format!(
+                "{} ({} changes were applied before the error)",
+                e.value(py),
+                applied
+            ),
+        );
+        error.set_cause(py, Some(e));
+        return Err(error);
+    }
+    let mut state = lock_document(
+        &doc.automerge,
+        |automerge| automerge.write(),
+        |state| state.doc.is_some(),
+    )?;
+    let doc_ = state
+        .doc
+        .as_mut()
+        .ok_or(AutomergeError::UsingDocDuringTransaction)?;
+    let patches = doc_
+        .make_patches(&mut patch_log)
+        .into_iter()
+        .map(|patch| Patch::new(py, &doc.automerge, patch, None))
+        .collect::<PyResult<Vec<_>>>()?;
+    drop(state);
+    // the callback is called without holding the lock, so it can read the document
+    if let Some(on_patch) = on_patch {
+        for patch in patches {
+            on_patch.call1((patch,))?;
+        }
+    }
+    Ok(ApplyResult {
+        applied,
+        pending: pending.iter().copied().map(PyChangeHash).collect(),
+    })
 }
 
-// This converts from a python value to a Automerge value and creates the appropriate transaction to write that value to the document
-fn apply_value(
-    tx: &mut Tx,
-    obj: impl AsRef<ObjId>,
-    prop: impl Into<Prop>,
-    value: AutomergeValue,
-) -> Result<(), PyErr> {
-    match_value!(value,
-        Scalar(s) => {
-            tx.put(obj, prop, s).map_err(AutomergeError::AutomergeError)?;
-        },
-        Sequence(s) => {
-            // TODO(robin): sequence creation could be optimized:
-            // 1. remove the dummy splice by adding a flag to apply_value to do insertion instead of puts
-            // 2. replace the dummy splice with a real splice if all values are ScalarValues
-            let sequence_id = tx.put_object(obj, prop, ObjType::List).map_err(AutomergeError::AutomergeError)?;
-            // insert dummy values for all new entries in the list
-            tx.splice(sequence_id.clone(), 0, 0, std::iter::repeat(ScalarValue::Null).take(s.len()?)).map_err(AutomergeError::AutomergeError)?;
-            for (i, elem) in s.iter()?.enumerate() {
-                apply_value(tx, sequence_id.clone(), i, elem?.extract()?)?;
-            }
-        },
-        Mapping(m) => {
-            let mapping_id = tx.put_object(obj, prop, ObjType::Map).map_err(AutomergeError::AutomergeError)?;
-            for entry in m.items()?.iter()? {
-                let (name, elem): (&str, AutomergeValue) = entry?.extract()?;
-                apply_value(tx, mapping_id.clone(), name, elem)?;
-            }
-        },
-        Text(t) => {
-            let text_id = tx.put_object(obj, prop, ObjType::Text).map_err(AutomergeError::AutomergeError)?;
-            // overwrite the complete text
-            tx.splice_text(text_id, 0, 0, &t.borrow().text).map_err(AutomergeError::AutomergeError)?;
-        },
-    );
+// Returns the hashes of the changes passed to apply_changes which are still waiting for their dependencies.
+// Unlike ApplyResult.pending, this also reflects dependencies supplied by merge, load_incremental or sync
+#[pyfunction]
+pub fn get_pending_changes(doc: &Document) -> PyResult<Vec<PyChangeHash>> {
+    let state = lock_document(
+        &doc.automerge,
+        |automerge| automerge.read(),
+        |state| state.doc.is_some(),
+    )?;
+    Ok(state
+        .pending_changes()
+        .into_iter()
+        .map(PyChangeHash)
+        .collect())
+}
 
-    Ok(())
+// Returns the hashes of the changes which are needed to apply the pending changes
+// If heads are given, these are also checked for
+#[pyfunction]
+#[pyo3(signature = (doc, heads=None))]
+pub fn get_missing_deps(
+    doc: &Document,
+    heads: Option<Vec<HashArg<'_>>>,
+) -> PyResult<Vec<PyChangeHash>> {
+    let heads = extract_heads(heads.unwrap_or_default())?;
+    Ok(with_doc!(doc, |doc| {
+        doc.get_missing_deps(&heads)
+            .iter()
+            .copied()
+            .map(PyChangeHash)
+            .collect()
+    }))
+}
 
-    // put
-    // put_object
-    // insert
-    // insert_object
-    // increment
-    // delete
-    // splice
-    // splice_text
+#[pyfunction]
+pub fn get_last_local_change(doc: &Document) -> PyResult<Option<Change>> {
+    Ok(with_doc!(doc, |doc| {
+        doc.get_last_local_change().map(|change| Change {
+            change: change.clone(),
+        })
+    }))
 }
 
-// special class for unknown automerge values
-#[pyclass]
-struct Unknown {
-    type_code: u8,
-    bytes: Vec<u8>,
+#[pyfunction]
+pub fn get_heads(doc: &Document) -> PyResult<Vec<PyChangeHash>> {
+    Ok(with_doc!(doc, |doc| {
+        doc.get_heads().iter().copied().map(PyChangeHash).collect()
+    }))
 }
 
-// special class for the automerge Text value which is basically a List that only supports unicode codepoints as values
-#[pyclass]
-#[derive(Debug)]
-struct Text {
-    text: String,
+// converts a list of hashes given as ChangeHash, bytes or hex strings into change hashes
+fn extract_heads(heads: Vec<HashArg<'_>>) -> Result<Vec<ChangeHash>, AutomergeError> {
+    heads.into_iter().map(ChangeHash::try_from).collect()
 }
 
-#[pymethods]
-impl Text {
-    #[new]
-    fn new(text: String) -> Self {
-        Self { text }
-    }
+// Change hashes can be given as ChangeHash, raw bytes or as a hex string
+#[derive(FromPyObject)]
+pub enum HashArg<'a> {
+    Hash(PyChangeHash),
+    Bytes(&'a PyBytes),
+    Hex(&'a str),
+}
 
-    fn __str__(&self) -> String {
-        self.text.clone()
+impl<'a> TryFrom<HashArg<'a>> for ChangeHash {
+    type Error = AutomergeError;
+
+    fn try_from(hash: HashArg<'a>) -> Result<Self, Self::Error> {
+        match hash {
+            HashArg::Hash(hash) => Ok(hash.0),
+            HashArg::Bytes(bytes) => bytes
+                .as_bytes()
+                .try_into()
+                .map_err(AutomergeError::InvalidChangeHashSlice),
+            HashArg::Hex(hex) => hex.parse().map_err(AutomergeError::InvalidChangeHash),
+        }
     }
 }
 
-// special class for automerge Counters, which support incremeting
-#[pyclass]
+// The hash of a change, printed as 64 hex characters, bytes() gives the raw 32 bytes.
+// Hashes compare like their raw bytes (and equal to them), so code comparing with bytes keeps working
+#[pyclass(name = "ChangeHash")]
 #[derive(Clone, Debug)]
-struct Counter(i64);
+pub struct PyChangeHash(ChangeHash);
 
 #[pymethods]
-impl Counter {
+impl PyChangeHash {
     #[new]
-    fn new(value: i64) -> Self {
-        Self(value)
+    fn new(hash: HashArg<'_>) -> PyResult<Self> {
+        Ok(PyChangeHash(hash.try_into()?))
     }
 
-    fn get(&self) -> i64 {
-        self.0
+    fn __str__(&self) -> String {
+        self.0.to_string()
     }
-}
 
-impl From<Counter> for ScalarValue {
-    fn from(counter: Counter) -> ScalarValue {
-        ScalarValue::Counter(counter.0.into())
+    // shows the first 7 hex characters like git, str() gives the complete hash
+    fn __repr__(&self) -> String {
+        format!("ChangeHash({:.7})", self.0.to_string())
     }
-}
 
-#[pyfunction]
-pub fn fork(py: Python<'_>, doc: &Document) -> PyResult<PyObject> {
-    let new_doc = with_doc!(doc, |doc| { doc.fork() });
+    fn __bytes__<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, self.0.as_ref())
+    }
 
-    Document::from_doc(py, new_doc)
+    // like bytes.hex(), for code written when hashes were bytes
+    fn hex(&self) -> String {
+        self.0.to_string()
+    }
+
+    // ordered like their bytes, so lists of heads can be sorted
+    fn __richcmp__(&self, py: Python<'_>, other: &PyAny, op: CompareOp) -> PyObject {
+        let other = match other.extract::<PyRef<'_, PyChangeHash>>() {
+            Ok(hash) => hash.0,
+            Err(_) => match other.downcast::<PyBytes>() {
+                Ok(bytes) => {
+                    return op
+                        .matches(self.0.as_ref().cmp(bytes.as_bytes()))
+                        .into_py(py)
+                }
+                Err(_) => return py.NotImplemented(),
+            },
+        };
+        op.matches(self.0.cmp(&other)).into_py(py)
+    }
+
+    // the hash of the raw bytes, as hashes are equal to them
+    fn __hash__(&self, py: Python<'_>) -> PyResult<isize> {
+        PyBytes::new(py, self.0.as_ref()).hash()
+    }
 }
 
+// Returns the change with the given hash, or None if the document does not contain it
 #[pyfunction]
-pub fn merge(doc_a: &mut Document, doc_b: &mut Document) -> PyResult<()> {
-    Ok(with_doc_mut!(doc_a, |doc_a| {
-        with_doc_mut!(doc_b, |doc_b| {
-            doc_a.merge(doc_b).map_err(AutomergeError::AutomergeError)?;
+pub fn get_change_by_hash(doc: &Document, hash: HashArg<'_>) -> PyResult<Option<Change>> {
+    let hash = ChangeHash::try_from(hash)?;
+    Ok(with_doc!(doc, |doc| {
+        doc.get_change_by_hash(&hash).map(|change| Change {
+            change: change.clone(),
         })
     }))
 }
 
+// Returns all changes which are not ancestors of the given heads in causal order
 #[pyfunction]
-pub fn save(py: Python<'_>, doc: &mut Document) -> PyResult<Py<PyBytes>> {
-    Ok(with_doc_mut!(doc, |doc| {
-        PyBytes::new(py, &doc.save()[..]).into()
+pub fn get_changes(doc: &Document, heads: Vec<HashArg<'_>>) -> PyResult<Vec<Change>> {
+    let heads = extract_heads(heads)?;
+    Ok(with_doc!(doc, |doc| {
+        doc.get_changes(&heads)
+            .into_iter()
+            .map(|change| Change {
+                change: change.clone(),
+            })
+            .collect()
     }))
 }
 
+// Returns the changes made with the actor of the document which are not ancestors of the given heads in causal order,
+// e.g. the local changes which still need to be uploaded since the heads of the last upload
 #[pyfunction]
-pub fn load(py: Python<'_>, bytes: &PyBytes) -> PyResult<PyObject> {
-    let new_doc = Automerge::load(bytes.as_bytes()).map_err(AutomergeError::AutomergeError)?;
-    Document::from_doc(py, new_doc)
+pub fn get_local_changes(doc: &Document, since_heads: Vec<HashArg<'_>>) -> PyResult<Vec<Change>> {
+    let heads = extract_heads(since_heads)?;
+    Ok(with_doc!(doc, |doc| {
+        let actor = doc.get_actor();
+        doc.get_changes(&heads)
+            .into_iter()
+            .filter(|change| change.actor_id() == actor)
+            .map(|change| Change {
+                change: change.clone(),
+            })
+            .collect()
+    }))
+}
+
+// Iterates over the metadata of all changes of the document in causal order, see HistoryIterator
+#[pyfunction]
+pub fn history(doc: &Document) -> PyResult<HistoryIterator> {
+    let hashes = with_doc!(doc, |doc| {
+        doc.get_changes(&[])
+            .into_iter()
+            .map(|change| change.hash())
+            .collect::<Vec<_>>()
+    });
+    Ok(HistoryIterator {
+        document: doc.clone(),
+        hashes: hashes.into_iter(),
+        chunk: Vec::new().into_iter(),
+    })
 }
 
+// The changes of a document as they were when history() was called. Only their hashes are kept,
+// the metadata is read in chunks, so documents with many changes don't need all entries at once
 #[pyclass]
-#[derive(Clone)]
-pub struct Change {
-    change: automerge::Change,
+pub struct HistoryIterator {
+    document: Document,
+    hashes: std::vec::IntoIter<ChangeHash>,
+    chunk: std::vec::IntoIter<HistoryEntry>,
 }
 
 #[pymethods]
-impl Change {
-    #[new]
-    fn new(bytes: &PyBytes) -> PyResult<Self> {
-        Ok(Self {
-            change: automerge::Change::from_bytes(bytes.as_bytes().to_vec())
-                .map_err(AutomergeError::LoadChangeError)?,
-        })
+impl HistoryIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
     }
 
-    fn bytes(&mut self, py: Python<'_>) -> Py<PyBytes> {
-        PyBytes::new(py, &*self.change.bytes()).into()
+    fn __next__(mut slf: PyRefMut<'_, Self>, py: Python<'_>) -> PyResult<Option<HistoryEntry>> {
+        let slf = &mut *slf;
+        if slf.chunk.len() == 0 {
+            let hashes: Vec<_> = slf.hashes.by_ref().take(ENTRIES_CHUNK_SIZE).collect();
+            let document = &slf.document;
+            slf.chunk = with_doc!(document, |doc| {
+                hashes
+                    .iter()
+                    .filter_map(|hash| doc.get_change_by_hash(hash))
+                    .map(|change| HistoryEntry::new(py, change))
+                    .collect::<Vec<_>>()
+            })
+            .into_iter();
+        }
+        Ok(slf.chunk.next())
     }
 
-    fn decode(&mut self, py: Python<'_>) -> PyResult<ExpandedChange> {
-        Ok(ExpandedChange {
-            change: self.change.decode(),
-        })
+    fn __length_hint__(&self) -> usize {
+        self.chunk.len() + self.hashes.len()
     }
 }
 
-#[pyclass]
-#[derive(Debug, Clone)]
-pub struct ExpandedChange {
-    change: automerge::ExpandedChange,
+// The metadata of a change, read without decoding its operations
+#[pyclass(get_all)]
+pub struct HistoryEntry {
+    hash: PyChangeHash,
+    actor_id: Py<PyBytes>,
+    seq: u64,
+    message: Option<String>,
+    // milliseconds since the unix epoch, as recorded by the author
+    timestamp: i64,
+    // the number of operations in the change
+    operations: usize,
+}
+
+impl HistoryEntry {
+    fn new(py: Python<'_>, change: &automerge::Change) -> Self {
+        HistoryEntry {
+            hash: PyChangeHash(change.hash()),
+            actor_id: PyBytes::new(py, change.actor_id().to_bytes()).into(),
+            seq: change.seq(),
+            message: change.message().cloned(),
+            timestamp: change.timestamp(),
+            operations: change.len(),
+        }
+    }
 }
 
 #[pymethods]
-impl ExpandedChange {
+impl HistoryEntry {
+    fn __repr__(&self, py: Python<'_>) -> String {
+        format!(
+            "HistoryEntry(hash={:.7}, actor={}, seq={}, message={}, operations={})",
+            self.hash.0.to_string(),
+            ActorId::from(self.actor_id.as_ref(py).as_bytes()),
+            self.seq,
+            match &self.message {
+                Some(message) => format!("{message:?}"),
+                None => "None".to_string(),
+            },
+            self.operations
+        )
+    }
+}
+
+// The operation which wrote a value, see modified_info
+#[pyclass(get_all)]
+pub struct ModifiedInfo {
+    // the id of the operation, as returned by get_all
+    opid: String,
+    actor_id: Py<PyBytes>,
+    // the hash, message and time (milliseconds since the unix epoch) of the change containing the operation,
+    // None if the change isn't part of the document
+    hash: Option<PyChangeHash>,
+    message: Option<String>,
+    timestamp: Option<i64>,
+}
+
+impl ModifiedInfo {
+    // describes the operation counter@actor, which is part of change
+    fn new(
+        py: Python<'_>,
+        change: Option<&automerge::Change>,
+        counter: u64,
+        actor: &ActorId,
+    ) -> Self {
+        ModifiedInfo {
+            opid: format!("{counter}@{actor}"),
+            actor_id: PyBytes::new(py, actor.to_bytes()).into(),
+            hash: change.map(|change| PyChangeHash(change.hash())),
+            message: change.and_then(|change| change.message().cloned()),
+            timestamp: change.map(|change| change.timestamp()),
+        }
+    }
+}
+
+#[pymethods]
+impl ModifiedInfo {
     fn __repr__(&self) -> String {
-        format!("{:?}", self)
+        format!(
+            "ModifiedInfo(opid={}, message={}, timestamp={})",
+            self.opid,
+            match &self.message {
+                Some(message) => format!("{message:?}"),
+                None => "None".to_string(),
+            },
+            match self.timestamp {
+                Some(timestamp) => timestamp.to_string(),
+                None => "None".to_string(),
+            }
+        )
+    }
+}
+
+// A run of text with the same marks (as a dict of name to value), see TextView.spans
+#[pyclass(get_all)]
+pub struct TextSpan {
+    text: String,
+    marks: Py<PyDict>,
+}
+
+#[pymethods]
+impl TextSpan {
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        Ok(format!(
+            "TextSpan({}, marks={})",
+            self.text.to_object(py).as_ref(py).repr()?,
+            self.marks.as_ref(py).repr()?
+        ))
+    }
+}
+
+// A block marker in a text (e.g. the start of a paragraph or list item) with its attributes,
+// see TextView.spans and TextTransaction.split_block
+#[pyclass(get_all)]
+pub struct BlockMarker {
+    attrs: Py<PyDict>,
+}
+
+#[pymethods]
+impl BlockMarker {
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        Ok(format!("BlockMarker({})", self.attrs.as_ref(py).repr()?))
     }
 }
 
+// Returns the changes of doc_a which are missing in doc_b,
+// applying them to doc_b brings it up to date with doc_a
 #[pyfunction]
-pub fn apply_changes(doc: &mut Document, changes: &PySequence) -> PyResult<()> {
-    Ok(with_doc_mut!(doc, |doc| {
-        for change in changes.iter()? {
-            let change = change?;
-            let change = if let Ok(change) = change.downcast::<PyBytes>() {
-                automerge::Change::from_bytes(change.as_bytes().to_vec())
-                    .map_err(AutomergeError::LoadChangeError)?
+pub fn get_changes_added(doc_a: &Document, doc_b: &Document) -> PyResult<Vec<Change>> {
+    if Arc::ptr_eq(&doc_a.automerge, &doc_b.automerge) {
+        return Ok(vec![]);
+    }
+    // the documents are locked in the same order as in merge, so the two can't deadlock
+    let a_first = Arc::as_ptr(&doc_a.automerge) < Arc::as_ptr(&doc_b.automerge);
+    let (first, second) = if a_first {
+        (doc_a, doc_b)
+    } else {
+        (doc_b, doc_a)
+    };
+    Ok(with_doc!(first, |first| {
+        with_doc!(second, |second| {
+            let (a, b) = if a_first {
+                (first, second)
             } else {
-                Change::extract(change)?.change
+                (second, first)
             };
-            doc.apply_changes(std::iter::once(change))
-                .map_err(AutomergeError::AutomergeError)?;
+            b.get_changes_added(a)
+                .into_iter()
+                .map(|change| Change {
+                    change: change.clone(),
+                })
+                .collect()
+        })
+    }))
+}
+
+// Actor ids can be given as raw bytes or as a hex string
+#[derive(FromPyObject)]
+pub enum Actor<'a> {
+    Bytes(&'a PyBytes),
+    Hex(&'a str),
+}
+
+impl<'a> TryFrom<Actor<'a>> for ActorId {
+    type Error = AutomergeError;
+
+    fn try_from(actor: Actor<'a>) -> Result<Self, Self::Error> {
+        match actor {
+            Actor::Bytes(bytes) => Ok(ActorId::from(bytes.as_bytes())),
+            Actor::Hex(hex) => ActorId::try_from(hex).map_err(AutomergeError::InvalidActorId),
+        }
+    }
+}
+
+// Sets what happens when the document is used while a transaction owns it
+// (only if the transaction was opened with snapshot=False, otherwise the snapshot is read):
+// "fail" raises an error right away (the default), "wait" waits until the transaction is commited or rolled back,
+// raising TransactionWaitTimeout if that takes longer than timeout seconds.
+// Waiting also applies to opening a transaction and to merge. The thread which opened the transaction
+// can't wait for it, using the document there fails right away
+#[pyfunction]
+#[pyo3(signature = (doc, behavior, timeout=None))]
+pub fn set_read_behavior(doc: &Document, behavior: &str, timeout: Option<f64>) -> PyResult<()> {
+    let behavior = match (behavior, timeout) {
+        ("fail", None) => ReadBehavior::Fail,
+        ("fail", Some(_)) => {
+            return Err(PyValueError::new_err(
+                "a timeout can only be given for the \"wait\" behavior",
+            ))
+        }
+        ("wait", Some(timeout)) if !(0.0..=u32::MAX as f64).contains(&timeout) => {
+            return Err(PyValueError::new_err(format!("invalid timeout {timeout}")))
+        }
+        ("wait", timeout) => ReadBehavior::Wait(timeout.map(Duration::from_secs_f64)),
+        (behavior, _) => {
+            return Err(PyValueError::new_err(format!(
+                "unknown read behavior {behavior:?}, expected \"fail\" or \"wait\""
+            )))
         }
+    };
+    lock_document(&doc.automerge, |automerge| automerge.write(), |_| true)?.read_behavior =
+        behavior;
+    Ok(())
+}
+
+#[pyfunction]
+pub fn get_actor(py: Python<'_>, doc: &Document) -> PyResult<Py<PyBytes>> {
+    Ok(with_doc!(doc, |doc| {
+        PyBytes::new(py, doc.get_actor().to_bytes()).into()
     }))
 }
 
 #[pyfunction]
-pub fn get_last_local_change(doc: &Document) -> PyResult<Option<Change>> {
+pub fn set_actor(doc: &mut Document, actor: Actor<'_>) -> PyResult<()> {
+    let actor = ActorId::try_from(actor)?;
+    with_doc_mut!(doc, |doc| {
+        doc.set_actor(actor);
+    });
+    Ok(())
+}
+
+// Holds the state of a sync session with a single peer.
+// A new SyncState should be used for every peer (and every new connection to a peer)
+#[pyclass]
+#[derive(Default)]
+pub struct SyncState {
+    state: automerge::sync::State,
+}
+
+#[pymethods]
+impl SyncState {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+
+    // the heads both peers are known to have
+    #[getter]
+    fn shared_heads(&self) -> Vec<PyChangeHash> {
+        self.state
+            .shared_heads
+            .iter()
+            .copied()
+            .map(PyChangeHash)
+            .collect()
+    }
+
+    // the heads the peer sent last, None before the first message of the peer was received
+    #[getter]
+    fn their_heads(&self) -> Option<Vec<PyChangeHash>> {
+        self.state
+            .their_heads
+            .as_ref()
+            .map(|heads| heads.iter().copied().map(PyChangeHash).collect())
+    }
+}
+
+// Returns the next message to send to the peer or None if there is nothing to send
+#[pyfunction]
+pub fn generate_sync_message(
+    py: Python<'_>,
+    doc: &Document,
+    state: &mut SyncState,
+) -> PyResult<Option<Py<PyBytes>>> {
     Ok(with_doc!(doc, |doc| {
-        doc.get_last_local_change().map(|change| Change {
-            change: change.clone(),
-        })
+        doc.generate_sync_message(&mut state.state)
+            .map(|message| PyBytes::new(py, &message.encode()).into())
     }))
 }
 
+#[pyfunction]
+pub fn receive_sync_message(
+    doc: &mut Document,
+    state: &mut SyncState,
+    message: &PyBytes,
+) -> PyResult<()> {
+    let message = automerge::sync::Message::decode(message.as_bytes())
+        .map_err(AutomergeError::ReadMessageError)?;
+    with_doc_mut!(doc, |doc| {
+        doc.receive_sync_message(&mut state.state, message)
+            .map_err(AutomergeError::AutomergeError)?;
+    });
+    Ok(())
+}
+
 #[derive(Debug)]
 pub enum AutomergeError {
-    NestedTransaction,
+    // describes the transaction which is already open
+    NestedTransaction(String),
     ReusedTransaction,
     UsingDocDuringTransaction,
     AutomergeError(automerge::AutomergeError),
     LoadChangeError(automerge::LoadChangeError),
+    ReadMessageError(automerge::sync::ReadMessageError),
+    InvalidChangeHashSlice(automerge::InvalidChangeHashSlice),
+    InvalidChangeHash(automerge::ParseChangeHashError),
+    LoadError(automerge::AutomergeError),
+    InvalidActorId(automerge::InvalidActorId),
+    ModifyingHistoricalView,
+    InvalidChunk(String),
+    StaleObject,
+    // a thread panicked while it had the document locked
+    PoisonedDocument,
 }
 
+// The exceptions raised for AutomergeError, all of them are ValueErrors so existing code catching those keeps working
+pyo3::create_exception!(
+    _backend,
+    AutomergeBaseError,
+    PyValueError,
+    "Base class of the errors raised by automerge"
+);
+pyo3::create_exception!(
+    _backend,
+    TransactionError,
+    AutomergeBaseError,
+    "Base class of the errors about transactions"
+);
+pyo3::create_exception!(
+    _backend,
+    TransactionInProgressError,
+    TransactionError,
+    "Raised when a document is used while a transaction owns it"
+);
+pyo3::create_exception!(
+    _backend,
+    TransactionReusedError,
+    TransactionError,
+    "Raised when a transaction is used after it was commited or rolled back"
+);
+pyo3::create_exception!(
+    _backend,
+    NestedTransactionError,
+    TransactionError,
+    "Raised when a transaction is opened while the document already has an open transaction"
+);
+pyo3::create_exception!(
+    _backend,
+    HistoricalViewError,
+    AutomergeBaseError,
+    "Raised when a historical view of a document is modified"
+);
+pyo3::create_exception!(
+    _backend,
+    StaleObjectError,
+    AutomergeBaseError,
+    "Raised when a proxy is used after its object was deleted from the document or overwritten"
+);
+pyo3::create_exception!(
+    _backend,
+    LoadError,
+    AutomergeBaseError,
+    "Raised when documents, changes or sync messages can't be decoded"
+);
+
 impl From<AutomergeError> for PyErr {
     fn from(error: AutomergeError) -> Self {
         match error {
-            AutomergeError::NestedTransaction => {
-                PyValueError::new_err("nested transactions are not allowed")
-            }
-            AutomergeError::ReusedTransaction => {
-                PyValueError::new_err("transaction was already commited, cannot use it again")
+            AutomergeError::NestedTransaction(open) if open.is_empty() => {
+                NestedTransactionError::new_err("nested transactions are not allowed")
             }
-            AutomergeError::UsingDocDuringTransaction => {
-                PyValueError::new_err("document used while there is a uncommited transaction")
+            AutomergeError::NestedTransaction(open) => NestedTransactionError::new_err(format!(
+                "nested transactions are not allowed, the document already has a transaction {open}"
+            )),
+            AutomergeError::ReusedTransaction => TransactionReusedError::new_err(
+                "transaction was already commited, cannot use it again",
+            ),
+            AutomergeError::ModifyingHistoricalView => {
+                HistoricalViewError::new_err("historical views of a document can not be modified")
             }
+            AutomergeError::UsingDocDuringTransaction => TransactionInProgressError::new_err(
+                "document used while there is a uncommited transaction",
+            ),
             AutomergeError::AutomergeError(e) => {
-                PyException::new_err(format!("Automerge error: {}", e))
+                AutomergeBaseError::new_err(format!("Automerge error: {}", e))
             }
             AutomergeError::LoadChangeError(e) => {
-                PyValueError::new_err(format!("LoadChangeError error: {}", e))
+                LoadError::new_err(format!("LoadChangeError error: {}", e))
+            }
+            AutomergeError::ReadMessageError(e) => {
+                LoadError::new_err(format!("ReadMessageError error: {}", e))
+            }
+            AutomergeError::InvalidChangeHashSlice(e) => {
+                AutomergeBaseError::new_err(format!("InvalidChangeHashSlice error: {}", e))
+            }
+            AutomergeError::InvalidChangeHash(e) => {
+                AutomergeBaseError::new_err(format!("invalid change hash: {}", e))
+            }
+            AutomergeError::LoadError(e) => LoadError::new_err(format!("Load error: {}", e)),
+            AutomergeError::InvalidChunk(e) => LoadError::new_err(format!("Load error: {}", e)),
+            AutomergeError::InvalidActorId(e) => AutomergeBaseError::new_err(format!("{}", e)),
+            AutomergeError::StaleObject => {
+                StaleObjectError::new_err("the object was deleted from the document or overwritten")
             }
+            AutomergeError::PoisonedDocument => AutomergeBaseError::new_err(
+                "the document can't be used anymore, a thread panicked while changing it",
+            ),
         }
     }
 }
@@ -1089,17 +5893,78 @@ fn _backend(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<DocumentTransaction>()?;
     m.add_class::<MappingTransaction>()?;
     m.add_class::<SequenceTransaction>()?;
+    m.add_class::<TextTransaction>()?;
     m.add_class::<Change>()?;
     m.add_class::<Text>()?;
+    m.add_class::<TextView>()?;
+    m.add_class::<Cursor>()?;
     m.add_class::<Counter>()?;
+    m.add_class::<BoundCounter>()?;
+    m.add_class::<Unknown>()?;
+    m.add_class::<SyncState>()?;
+    m.add_class::<PyChangeHash>()?;
+    m.add_class::<Patch>()?;
+    m.add(
+        "TransactionWaitTimeout",
+        _py.get_type::<TransactionWaitTimeout>(),
+    )?;
+    m.add("AutomergeBaseError", _py.get_type::<AutomergeBaseError>())?;
+    m.add("TransactionError", _py.get_type::<TransactionError>())?;
+    m.add(
+        "TransactionInProgressError",
+        _py.get_type::<TransactionInProgressError>(),
+    )?;
+    m.add(
+        "TransactionReusedError",
+        _py.get_type::<TransactionReusedError>(),
+    )?;
+    m.add(
+        "NestedTransactionError",
+        _py.get_type::<NestedTransactionError>(),
+    )?;
+    m.add("HistoricalViewError", _py.get_type::<HistoricalViewError>())?;
+    m.add("LoadError", _py.get_type::<LoadError>())?;
+    m.add("StaleObjectError", _py.get_type::<StaleObjectError>())?;
+    m.add_class::<ApplyResult>()?;
     m.add_function(wrap_pyfunction!(transaction, m)?)?;
+    m.add_function(wrap_pyfunction!(transact, m)?)?;
     m.add_function(wrap_pyfunction!(entries, m)?)?;
     m.add_function(wrap_pyfunction!(init, m)?)?;
     m.add_function(wrap_pyfunction!(fork, m)?)?;
+    m.add_function(wrap_pyfunction!(fork_at, m)?)?;
+    m.add_function(wrap_pyfunction!(at, m)?)?;
     m.add_function(wrap_pyfunction!(merge, m)?)?;
+    m.add_function(wrap_pyfunction!(merged, m)?)?;
     m.add_function(wrap_pyfunction!(load, m)?)?;
+    m.add_function(wrap_pyfunction!(save_to_file, m)?)?;
+    m.add_function(wrap_pyfunction!(load_from_file, m)?)?;
+    m.add_function(wrap_pyfunction!(to_json, m)?)?;
+    m.add_function(wrap_pyfunction!(load_json, m)?)?;
+    m.add_function(wrap_pyfunction!(load_incremental, m)?)?;
     m.add_function(wrap_pyfunction!(save, m)?)?;
+    m.add_function(wrap_pyfunction!(save_incremental, m)?)?;
     m.add_function(wrap_pyfunction!(apply_changes, m)?)?;
     m.add_function(wrap_pyfunction!(get_last_local_change, m)?)?;
+    m.add_function(wrap_pyfunction!(get_heads, m)?)?;
+    m.add_function(wrap_pyfunction!(get_actor, m)?)?;
+    m.add_function(wrap_pyfunction!(set_actor, m)?)?;
+    m.add_function(wrap_pyfunction!(set_read_behavior, m)?)?;
+    m.add_function(wrap_pyfunction!(get_changes, m)?)?;
+    m.add_function(wrap_pyfunction!(get_local_changes, m)?)?;
+    m.add_function(wrap_pyfunction!(history, m)?)?;
+    m.add_class::<HistoryEntry>()?;
+    m.add_class::<ModifiedInfo>()?;
+    m.add_class::<TextSpan>()?;
+    m.add_class::<BlockMarker>()?;
+    m.add_function(wrap_pyfunction!(get_changes_added, m)?)?;
+    m.add_function(wrap_pyfunction!(get_change_by_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(generate_sync_message, m)?)?;
+    m.add_function(wrap_pyfunction!(receive_sync_message, m)?)?;
+    m.add_function(wrap_pyfunction!(timestamp_millis, m)?)?;
+    m.add_function(wrap_pyfunction!(raw_timestamps, m)?)?;
+    m.add_class::<PyTimestamp>()?;
+    m.add_function(wrap_pyfunction!(diff, m)?)?;
+    m.add_function(wrap_pyfunction!(get_missing_deps, m)?)?;
+    m.add_function(wrap_pyfunction!(get_pending_changes, m)?)?;
     Ok(())
 }
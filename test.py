@@ -1,4 +1,6 @@
 #!/usr/bin/env python3
+import base64
+import datetime
 import automerge
 import json
 
@@ -10,7 +12,7 @@ def dump(doc: automerge.Document):
                 value = dump(value)
             elif isinstance(value, automerge.Counter):
                 value = value.get()
-            elif isinstance(value, automerge.Text):
+            elif isinstance(value, (automerge.Text, automerge.TextView)):
                 value = str(value)
             res[name] = value
     else: # sequence
@@ -20,7 +22,7 @@ def dump(doc: automerge.Document):
                 value = dump(value)
             elif isinstance(value, automerge.Counter):
                 value = value.get()
-            elif isinstance(value, automerge.Text):
+            elif isinstance(value, (automerge.Text, automerge.TextView)):
                 value = str(value)
             res.append(value)
 
@@ -122,3 +124,2023 @@ with automerge.transaction(ideas_doc) as d:
 
 
 dd(ideas_doc)
+
+
+sync_a = automerge.fork(doc_a)
+sync_b = automerge.fork(doc_b)
+
+with automerge.transaction(sync_a) as d:
+    d.from_a = "a"
+
+with automerge.transaction(sync_b) as d:
+    d.from_b = "b"
+
+state_a = automerge.SyncState()
+state_b = automerge.SyncState()
+
+while True:
+    msg_a = automerge.generate_sync_message(sync_a, state_a)
+    if msg_a is not None:
+        automerge.receive_sync_message(sync_b, state_b, msg_a)
+    msg_b = automerge.generate_sync_message(sync_b, state_b)
+    if msg_b is not None:
+        automerge.receive_sync_message(sync_a, state_a, msg_b)
+    if msg_a is None and msg_b is None:
+        break
+
+assert automerge.dump(sync_a) == automerge.dump(sync_b)
+assert sync_a.from_b == "b" and sync_b.from_a == "a"
+assert sorted(automerge.get_heads(sync_a)) == sorted(automerge.get_heads(sync_b))
+assert automerge.get_heads(sync_a.hello) == automerge.get_heads(sync_a)
+
+assert automerge.get_changes(sync_a, automerge.get_heads(sync_a)) == []
+replica = automerge.init()
+automerge.apply_changes(replica, [c.bytes() for c in automerge.get_changes(sync_a, [])])
+assert sorted(automerge.get_heads(replica)) == sorted(automerge.get_heads(sync_a))
+
+log = automerge.save(sync_a)
+assert automerge.save_incremental(sync_a) == b""
+with automerge.transaction(sync_a) as d:
+    d.appended = 1
+chunk = automerge.save_incremental(sync_a)
+assert chunk != b""
+assert automerge.save_incremental(sync_a) == b""
+restored = automerge.load(log + chunk)
+assert automerge.get_heads(restored) == automerge.get_heads(sync_a)
+# loaded and forked documents only save what was changed after loading or forking them
+assert automerge.save_incremental(restored) == b""
+assert automerge.save_incremental(automerge.fork(restored)) == b""
+with automerge.transaction(restored) as d:
+    d.appended = 2
+assert automerge.load(log + chunk + automerge.save_incremental(restored)).appended == 2
+
+restored = automerge.load(log)
+assert automerge.load_incremental(restored, chunk) > 0
+assert automerge.get_heads(restored) == automerge.get_heads(sync_a)
+try:
+    automerge.load_incremental(restored, b"\x85\x6f\x4a\x83garbage")
+    assert False
+except ValueError:
+    pass
+# whole saved documents can be loaded incrementally too, as long as their checksum matches
+merged_save = automerge.init({"other": 1})
+assert automerge.load_incremental(merged_save, log + chunk) > 0
+assert merged_save.appended == 1 and merged_save.other == 1
+corrupted = automerge.init()
+try:
+    automerge.load_incremental(corrupted, log[:-1] + bytes([log[-1] ^ 1]))
+    assert False
+except ValueError as e:
+    assert "checksum" in str(e), str(e)
+assert automerge.get_heads(corrupted) == []
+# the checksum covers the length as it is encoded, padding it is left to automerge to reject
+import hashlib
+assert log[9] & 0x80 and not log[10] & 0x80 and len(log) == 11 + (log[9] & 0x7f) + (log[10] << 7)
+padded_header = bytes([log[8], log[9], log[10] | 0x80, 0])
+padded = log[:4] + hashlib.sha256(padded_header + log[11:]).digest()[:4] + padded_header + log[11:]
+try:
+    automerge.load_incremental(corrupted, padded)
+    assert False
+except automerge.LoadError as e:
+    assert "checksum" not in str(e) and "leb128" in str(e), str(e)
+
+automerge.set_actor(sync_a, "deadbeef")
+assert automerge.get_actor(sync_a) == bytes.fromhex("deadbeef")
+with automerge.transaction(sync_a) as d:
+    d.by_actor = True
+assert "deadbeef" in repr(automerge.get_last_local_change(sync_a).decode())
+automerge.set_actor(sync_a, b"\x01\x02")
+assert automerge.get_actor(sync_a) == b"\x01\x02"
+
+old_heads = automerge.get_heads(sync_a)
+with automerge.transaction(sync_a) as d:
+    d.after_fork_point = True
+draft = automerge.fork_at(sync_a, old_heads)
+assert automerge.get_heads(draft) == old_heads
+assert not hasattr(draft, "after_fork_point")
+assert automerge.get_actor(draft) != automerge.get_actor(sync_a)
+assert automerge.dump(automerge.fork_at(sync_a, automerge.get_heads(sync_a))) == automerge.dump(automerge.fork(sync_a))
+try:
+    automerge.fork_at(sync_a, [bytes(32)])
+    assert False
+except ValueError as e:
+    assert "00" * 32 in str(e)
+
+with automerge.transaction(sync_a) as d:
+    d.revision = {"title": "v1", "items": [1, 2], "body": automerge.Text("old")}
+v1 = automerge.get_heads(sync_a)
+with automerge.transaction(sync_a) as d:
+    d.revision.title = "v2"
+    d.revision["items"].append(3)
+    d.revision.body[0:3] = "new"
+old = automerge.at(sync_a, v1)
+assert old.revision.title == "v1" and sync_a.revision.title == "v2"
+assert list(old.revision["items"]) == [1, 2] and len(sync_a.revision["items"]) == 3
+assert str(old.revision.body) == "old" and str(sync_a.revision.body) == "new"
+assert dict(automerge.entries(old.revision))["title"] == "v1"
+try:
+    automerge.transaction(old)
+    assert False
+except ValueError:
+    pass
+
+with automerge.transaction(sync_a) as d:
+    d.config = {"theme": "dark", "nested": {"a": 1}, "hits": automerge.Counter(1)}
+    assert d.config.keys() == ["hits", "nested", "theme"]
+    assert dict(d.config.items())["theme"] == "dark"
+    dict(d.config.items())["hits"].increment(1)
+assert sync_a.config.keys() == ["hits", "nested", "theme"]
+config = dict(sync_a.config.items())
+assert config["theme"] == "dark" and config["nested"].a == 1 and config["hits"].get() == 2
+assert len(sync_a.config.values()) == 3
+
+with automerge.transaction(sync_a) as d:
+    d.ordered = {"b": 1, "a": 2, "c": 3}
+assert [key for key in sync_a.ordered] == ["a", "b", "c"]
+assert list(sync_a.ordered) == sync_a.ordered.keys()
+assert dict(sync_a.ordered) == {"a": 2, "b": 1, "c": 3}
+
+with automerge.transaction(sync_a) as d:
+    d.ordered.deleted_at = None
+    assert "deleted_at" in d.ordered and "never_set" not in d.ordered
+assert "deleted_at" in sync_a.ordered and "never_set" not in sync_a.ordered
+
+assert sync_a.ordered.get("a") == 2
+assert sync_a.ordered.get("missing") is None
+assert sync_a.ordered.get("missing", 42) == 42
+assert sync_a.ordered.get("deleted_at", 42) is None
+assert sync_a.get("ordered").get("b") == 1
+
+try:
+    sync_a["doesnotexist"]
+    assert False
+except KeyError:
+    pass
+assert sync_a.ordered["deleted_at"] is None
+with automerge.transaction(sync_a) as d:
+    try:
+        d["doesnotexist"]
+        assert False
+    except KeyError:
+        pass
+    assert d.ordered["deleted_at"] is None
+
+assert not hasattr(sync_a, "doesnotexist") and hasattr(sync_a, "ordered")
+assert not hasattr(sync_a, "__getnewargs_ex__") and hasattr(sync_a, "__deepcopy__")
+with automerge.transaction(sync_a) as d:
+    assert not hasattr(d, "doesnotexist") and hasattr(d, "ordered")
+
+with automerge.transaction(sync_a) as d:
+    d.rows = [1, "two", {"three": 3}]
+    assert [row for row in d.rows][:2] == [1, "two"]
+rows = [row for row in sync_a.rows]
+assert rows[:2] == [1, "two"] and rows[2].three == 3
+shrinking = iter(sync_a.rows)
+next(shrinking)
+with automerge.transaction(sync_a) as d:
+    del d.rows[2]
+    del d.rows[1]
+assert list(shrinking) == []
+
+with automerge.transaction(sync_a) as d:
+    d.fruits = ["apple", 1, 2.5, True, b"\x00", {"nested": 1}]
+    assert "apple" in d.fruits and "pear" not in d.fruits
+for needle in ["apple", 1, 2.5, True]:
+    assert needle in sync_a.fruits
+assert "pear" not in sync_a.fruits and {"nested": 1} not in sync_a.fruits
+
+# slicing a list returns a plain python list
+with automerge.transaction(sync_a) as d:
+    d.digits = list(range(10))
+assert sync_a.digits[1:5] == [1, 2, 3, 4]
+assert sync_a.digits[-3:] == [7, 8, 9]
+assert sync_a.digits[::3] == [0, 3, 6, 9]
+assert sync_a.digits[::-4] == [9, 5, 1]
+assert sync_a.digits[5:100] == [5, 6, 7, 8, 9]
+assert sync_a.digits[100:] == []
+assert sync_a.digits[-1] == 9
+
+# negative indices in list transactions
+with automerge.transaction(sync_a) as d:
+    d.digits[-1] = 90
+    del d.digits[-2]
+    d.digits[len(d.digits)] = 10
+    for index in [-12, 11]:
+        for write in [lambda: d.digits.__setitem__(index, 0), lambda: d.digits.__delitem__(index)]:
+            try:
+                write()
+                assert False
+            except IndexError as e:
+                assert "out of range for list of length 10" in str(e)
+assert sync_a.digits[-3:] == [7, 90, 10]
+
+with automerge.transaction(sync_a) as d:
+    d.duplicates = [1, 3, "x", 3]
+    d.duplicates.remove(3)
+    assert [item for item in d.duplicates] == [1, "x", 3]
+    try:
+        d.duplicates.remove("y")
+        assert False
+    except ValueError:
+        pass
+assert [item for item in sync_a.duplicates] == [1, "x", 3]
+
+with automerge.transaction(sync_a) as d:
+    d.tags = ["urgent", "x", {"x": 1}, "x", "urgent"]
+    assert d.tags.index("urgent") == 0 and d.tags.index("urgent", 1) == 4
+    assert d.tags.count("x") == 2 and d.tags.count({"x": 1}) == 0
+assert sync_a.tags.index("x") == 1 and sync_a.tags.index("x", -2) == 3
+assert sync_a.tags.count("urgent") == 2
+for args in [("missing",), ("urgent", 1, 4), ({"x": 1},)]:
+    try:
+        sync_a.tags.index(*args)
+        assert False
+    except ValueError:
+        pass
+
+with automerge.transaction(sync_a) as d:
+    d.drain = {"a": 1, "nested": {"list": [1, {"b": "c"}]}}
+    assert d.drain.pop("a") == 1 and "a" not in d.drain
+    assert d.drain.pop("a", None) is None and d.drain.pop("a", 5) == 5
+    try:
+        d.drain.pop("a")
+        assert False
+    except KeyError:
+        pass
+    assert d.drain.popitem() == ("nested", {"list": [1, {"b": "c"}]})
+    try:
+        d.drain.popitem()
+        assert False
+    except KeyError:
+        pass
+assert sync_a.drain.keys() == []
+
+with automerge.transaction(sync_a) as d:
+    speakers = d.setdefault("speakers", {})
+    speakers.alice = "Alice"
+    assert d.setdefault("speakers", {"other": 1}).alice == "Alice"
+    d.setdefault("order", []).append("alice")
+    assert d.setdefault("title", "untitled") == "untitled"
+    assert d.setdefault("title", "other") == "untitled"
+    assert d.setdefault("nothing") is None and "nothing" in d
+assert sync_a.speakers.alice == "Alice" and sync_a.order[0] == "alice"
+
+assert list(automerge.entries(sync_a.digits))[:2] == [(0, 0), (1, 1)]
+assert [index for index, _ in automerge.entries(sync_a.digits)] == list(range(10))
+assert list(automerge.entries(old.revision["items"])) == [(0, 1), (1, 2)]
+
+with automerge.transaction(sync_a) as d:
+    d.many = {f"k{i:04}": i for i in range(600)}
+many = automerge.entries(sync_a.many)
+assert many.__length_hint__() == 600
+assert next(many) == ("k0000", 0)
+with automerge.transaction(sync_a) as d:
+    del d.many["k0001"]
+    del d.many["k0400"]
+rest = list(many)
+assert len(rest) == 597 and ("k0400", 400) not in rest and rest[0] == ("k0002", 2)
+assert len(list(automerge.entries(automerge.at(sync_a, v1)))) == len(list(automerge.at(sync_a, v1).keys()))
+
+with automerge.transaction(sync_a) as d:
+    d.deleted_at = None
+    d.nulls = {"x": None, "list": [None, 1]}
+    d.nulls.list.append(None)
+    assert d.deleted_at is None and "deleted_at" in d
+assert sync_a.deleted_at is None and "deleted_at" in sync_a
+assert sync_a.nulls.x is None and "x" in sync_a.nulls
+assert list(sync_a.nulls.list) == [None, 1, None]
+
+created = datetime.datetime(2024, 1, 2, 3, 4, 5, 678999, tzinfo=datetime.timezone.utc)
+with automerge.transaction(sync_a) as d:
+    d.created = created
+    d.events = {"at": [created.astimezone(datetime.timezone(datetime.timedelta(hours=2)))]}
+    d.before_epoch = datetime.datetime(1969, 12, 31, 23, 59, 59, 500000, tzinfo=datetime.timezone.utc)
+assert sync_a.created == created.replace(microsecond=678000)
+assert sync_a.events.at[0] == sync_a.created and sync_a.created.tzinfo == datetime.timezone.utc
+assert automerge.timestamp_millis(sync_a.created) == 1704164645678
+assert automerge.timestamp_millis(sync_a.before_epoch) == -500
+# timestamps outside of the range of datetime and within raw_timestamps() are read as raw milliseconds
+with automerge.transaction(sync_a) as d:
+    d.far_future = automerge.Timestamp(8_640_000_000_000_000)
+    d.raw_copy = automerge.Timestamp(automerge.timestamp_millis(created))
+assert sync_a.far_future == automerge.Timestamp(8_640_000_000_000_000) and sync_a.far_future.millis == 8_640_000_000_000_000
+assert repr(sync_a.far_future) == "Timestamp(8640000000000000)" and automerge.timestamp_millis(sync_a.far_future) == 8_640_000_000_000_000
+assert sync_a.raw_copy == sync_a.created and "far_future" in sync_a.to_py()
+with automerge.raw_timestamps():
+    assert sync_a.created == automerge.Timestamp(1704164645678) and sync_a.events.at[0].millis == 1704164645678
+    with automerge.raw_timestamps():
+        assert sync_a.to_py()["before_epoch"] == automerge.Timestamp(-500)
+    assert sync_a.before_epoch == automerge.Timestamp(-500)
+    with automerge.transaction(sync_a) as d:
+        d.copied = d.created
+assert sync_a.copied == sync_a.created and sync_a.created.tzinfo == datetime.timezone.utc
+try:
+    with automerge.transaction(sync_a) as d:
+        d.naive = datetime.datetime(2024, 1, 2)
+    assert False
+except TypeError as e:
+    assert "naive" in str(e)
+
+stored = sync_a.created
+with automerge.transaction(sync_a) as d:
+    assert d.created == stored
+    d.copied = d.created
+assert automerge.dump(sync_a)["copied"] == sync_a.created
+
+thumbnail = bytes(range(256))
+with automerge.transaction(sync_a) as d:
+    d.blob = thumbnail
+    d.blobs = [b"\x00\x01\x02"]
+    assert d.blob == thumbnail and d.blobs[0] == b"\x00\x01\x02"
+    assert d.pop("blob") == thumbnail
+    d.blob = thumbnail
+assert sync_a.blob == thumbnail and sync_a["blobs"][0] == b"\x00\x01\x02"
+assert dict(automerge.entries(sync_a))["blob"] == thumbnail
+assert b"\x00" in sync_a.fruits
+
+sensor = bytes(range(16))
+with automerge.transaction(sync_a) as d:
+    d.chunks = {"array": bytearray(sensor), "view": memoryview(sensor)[4:8]}
+    d.chunks.strided = memoryview(sensor)[::2]
+assert sync_a.chunks.array == sensor and sync_a.chunks.view == sensor[4:8]
+assert sync_a.chunks.strided == sensor[::2]
+try:
+    import numpy
+except ImportError:
+    numpy = None
+if numpy is not None:
+    with automerge.transaction(sync_a) as d:
+        d.chunks.numpy = numpy.arange(8, dtype=numpy.uint8)
+    assert sync_a.chunks.numpy == bytes(range(8))
+
+with automerge.transaction(sync_a) as d:
+    d.ints = {"max_i64": 2**63 - 1, "min_i64": -2**63, "u64": 2**63, "max_u64": 2**64 - 1}
+assert sync_a.ints.max_i64 == 2**63 - 1 and sync_a.ints.min_i64 == -2**63
+assert sync_a.ints.u64 == 2**63 and sync_a.ints.max_u64 == 2**64 - 1
+for value in [2**64, 2**70, -2**63 - 1, -2**70]:
+    try:
+        with automerge.transaction(sync_a) as d:
+            d.n = value
+        assert False
+    except OverflowError as e:
+        assert str(value) in str(e) and str(e).endswith(" at n")
+try:
+    with automerge.transaction(sync_a) as d:
+        d.big = [1, 2**64]
+    assert False
+except OverflowError as e:
+    assert str(e).endswith(" at big[1]")
+
+future = automerge.Unknown(12, b"\x01\x02")
+assert future.type_code == 12 and future.bytes == b"\x01\x02"
+assert repr(future) == "Unknown(type_code=12, bytes=0x0102)"
+assert future == automerge.Unknown(12, b"\x01\x02") and future != automerge.Unknown(13, b"\x01\x02")
+for type_code in [4, 16]:
+    try:
+        automerge.Unknown(type_code, b"")
+        assert False
+    except ValueError:
+        pass
+newer = automerge.init()
+with automerge.transaction(newer) as d:
+    d.future = future
+    d.other = 1
+reloaded = automerge.load(automerge.save(newer))
+with automerge.transaction(reloaded) as d:
+    d.other = 2
+reloaded = automerge.load(automerge.save(reloaded))
+assert reloaded.future == future and reloaded.other == 2
+
+with automerge.transaction(sync_a) as d:
+    d.likes = automerge.Counter(5)
+likes = sync_a.likes
+assert likes.get() == 5 and int(likes) == 5 and likes == 5 and likes == automerge.Counter(5)
+assert likes < 6 and likes > automerge.Counter(4) and not likes < 5 and likes != 4
+assert f"{likes}" == "5" and f"{likes:03d}" == "005" and repr(likes) == "Counter(5)"
+assert [0, 1, 2, 3, 4, 5][likes] == 5 and hash(likes) == hash(5) and {likes: 1}[5] == 1
+assert likes != "5"
+
+with automerge.transaction(sync_a) as d:
+    d.likes.increment(1)
+    d.likes.increment(1)
+    assert d.likes.get() == 7 and int(d.likes) == 7
+assert sync_a.likes == 7
+
+likes = sync_a.likes
+likes.increment(3)
+assert likes == 10 and sync_a.likes == 10 and isinstance(likes, automerge.Counter)
+with automerge.transaction(sync_a) as d:
+    d.counters = [automerge.Counter(1)]
+    try:
+        likes.increment(1)
+        assert False
+    except ValueError:
+        pass
+sync_a.counters[0].increment(2)
+assert sync_a.counters[0] == 3 and dict(automerge.entries(sync_a))["likes"] == 10
+
+base = automerge.init()
+with automerge.transaction(base) as d:
+    d.title = "base"
+    d.list = ["base"]
+left, right = automerge.fork(base), automerge.fork(base)
+with automerge.transaction(left) as d:
+    d.title = "left"
+    d.list[0] = {"side": "left"}
+with automerge.transaction(right) as d:
+    d.title = "right"
+    d.list[0] = "right"
+automerge.merge(left, right)
+conflicts = left.get_all("title")
+assert sorted(value for value, _ in conflicts) == ["left", "right"]
+actors = {automerge.get_actor(left).hex(), automerge.get_actor(right).hex()}
+assert {opid.split("@")[1] for _, opid in conflicts} == actors
+values = [value for value, _ in left.list.get_all(0)]
+assert "right" in values and any(getattr(value, "side", None) == "left" for value in values)
+assert len(base.get_all("title")) == 1 and base.get_all("missing") == []
+with automerge.transaction(left) as d:
+    assert len(d.get_all("title")) == 2 and len(d.list.get_all(0)) == 2
+    d.title = "resolved"
+    assert [value for value, _ in d.get_all("title")] == ["resolved"]
+try:
+    left.list.get_all(5)
+    assert False
+except IndexError:
+    pass
+
+with automerge.transaction(sync_a) as d:
+    d.status = {"state": "v1", "history": ["v1"]}
+status_v1 = automerge.get_heads(sync_a)
+with automerge.transaction(sync_a) as d:
+    d.status.state = "v2"
+    d.status.history.append("v2")
+assert sync_a.status.state == "v2" and sync_a.status.get_at("state", status_v1) == "v1"
+history = sync_a.status.get_at("history", status_v1)
+assert list(history) == ["v1"] and len(history) == 1
+assert sync_a.status.history.get_at(0, status_v1) == "v1"
+for call in [lambda: sync_a.status.get_at("state", [bytes(32)]), lambda: sync_a.status.history.get_at(1, status_v1), lambda: sync_a.get_at("status", v1)]:
+    try:
+        call()
+        assert False
+    except (ValueError, IndexError, KeyError):
+        pass
+
+current = automerge.get_heads(sync_a)
+# (the root is not used here, as automerge 0.5 hides incremented counters from historical reads)
+assert sync_a.status.keys_at(current) == sync_a.status.keys()
+assert sync_a.status.length_at(current) == len(sync_a.status) == 2
+assert sync_a.status.history.length_at(current) == len(sync_a.status.history) == 2
+assert sync_a.status.history.length_at(status_v1) == 1
+assert "status" not in sync_a.keys_at(v1) and sync_a.length_at(v1) == len(sync_a.keys_at(v1))
+assert dict(automerge.entries(sync_a.status, status_v1))["state"] == "v1"
+assert [value for _, value in automerge.entries(sync_a.status.history, status_v1)] == ["v1"]
+
+with automerge.transaction(sync_a) as d:
+    d.note = automerge.Text("first draft")
+    d.notes = [automerge.Text("list draft")]
+draft = automerge.get_heads(sync_a)
+with automerge.transaction(sync_a) as d:
+    d.note[0:5] = "final"
+    d.notes[0][0:4] = "LIST"
+assert str(sync_a.note) == "final draft" and sync_a.text_at("note", draft) == "first draft"
+assert sync_a.notes.text_at(0, draft) == "list draft"
+for call, error in [(lambda: sync_a.text_at("note", [bytes(32)]), ValueError), (lambda: sync_a.text_at("title", draft), TypeError), (lambda: sync_a.text_at("note", v1), KeyError), (lambda: sync_a.notes.text_at(1, draft), IndexError)]:
+    try:
+        call()
+        assert False
+    except error:
+        pass
+
+watched = automerge.init()
+with automerge.transaction(watched) as d:
+    d.title = "old"
+    d.gone = 1
+    d.items = [1, 2, 3]
+    d.body = automerge.Text("hello")
+    d.views = automerge.Counter(0)
+before = automerge.get_heads(watched)
+with automerge.transaction(watched) as d:
+    d.title = "new"
+    del d.gone
+    d["items"].append({"nested": True})
+    del d["items"][0]
+    d["items"][0] = 20
+    d.body[5:5] = " world"
+    d.body[0:1] = "H"
+    d.views.increment(3)
+after = automerge.get_heads(watched)
+assert automerge.diff(watched, before, before) == []
+patches = automerge.diff(watched, before, after)
+summary = [(p.action, p.path, p.key) for p in patches]
+assert ("put", [], "title") in summary and ("delete", [], "gone") in summary
+assert ("increment", [], "views") in summary
+put_title = next(p for p in patches if p.key == "title")
+assert put_title.value == "new" and "title" in repr(put_title)
+inserted = next(p for p in patches if p.action == "insert")
+assert inserted.path == ["items"] and inserted.value[0].nested is True
+assert any(p.action == "delete" and p.path == ["items"] and p.length == 1 for p in patches)
+assert any(p.action == "put" and p.path == ["items"] and p.value == 20 for p in patches)
+assert any(p.action == "splice_text" and p.path == ["body"] and p.value == " world" for p in patches)
+assert next(p for p in patches if p.action == "increment").value == 3
+reverse = automerge.diff(watched, after, before)
+assert any(p.action == "put" and p.key == "gone" and p.value == 1 for p in reverse)
+try:
+    automerge.diff(watched, before, [bytes(32)])
+    assert False
+except ValueError:
+    pass
+
+follower = automerge.load(automerge.save(watched))
+with automerge.transaction(watched) as d:
+    d.title = "newer"
+    d["items"].append(4)
+seen = []
+automerge.apply_changes(follower, [automerge.get_last_local_change(watched)], on_patch=lambda patch: seen.append((patch.action, patch.key, follower.title)))
+assert seen[0] == ("put", "title", "newer") and ("insert", 3, "newer") in seen
+with automerge.transaction(watched) as d:
+    d.title = "newest"
+def failing(patch):
+    raise RuntimeError("callback failed")
+try:
+    automerge.apply_changes(follower, [automerge.get_last_local_change(watched)], failing)
+    assert False
+except RuntimeError:
+    pass
+assert follower.title == "newest"
+with automerge.transaction(follower) as d:
+    d.title = "still usable"
+
+behind = automerge.fork(watched)
+with automerge.transaction(watched) as d:
+    d.title = "merged"
+merged_patches = automerge.merge(behind, watched)
+assert [(p.action, p.key, p.value) for p in merged_patches] == [("put", "title", "merged")]
+assert automerge.merge(behind, watched) == []
+
+preview_a = automerge.fork(watched)
+preview_b = automerge.fork(watched)
+with automerge.transaction(preview_a) as d:
+    d.a = 1
+with automerge.transaction(preview_b) as d:
+    d.b = 2
+preview = automerge.merged(preview_a, preview_b)
+assert preview.a == 1 and preview.b == 2
+assert "b" not in preview_a and "a" not in preview_b
+with automerge.transaction(preview) as d:
+    d.c = 3
+assert "c" not in preview_a
+
+source = automerge.init()
+replica = automerge.load(automerge.save(source))
+out_of_order, hashes = [], []
+for i in range(3):
+    with automerge.transaction(source) as d:
+        d.step = i
+    out_of_order.append(automerge.get_last_local_change(source))
+    hashes.append(automerge.get_heads(source)[0])
+result = automerge.apply_changes(replica, [out_of_order[2], out_of_order[1]])
+assert result.applied == 0 and len(result.pending) == 2
+assert sorted(result.pending) == sorted(hashes[1:]) and "pending=2" in repr(result)
+assert automerge.get_missing_deps(replica) == [hashes[0]]
+result = automerge.apply_changes(replica, [out_of_order[0]])
+assert result.applied == 3 and result.pending == [] and replica.step == 2
+assert automerge.get_missing_deps(replica) == []
+assert automerge.apply_changes(replica, [out_of_order[0]]).applied == 0
+
+withheld = automerge.init()
+automerge.apply_changes(withheld, [out_of_order[0], out_of_order[2]])
+assert automerge.get_missing_deps(withheld) == [hashes[1]]
+assert automerge.get_missing_deps(withheld, None) == [hashes[1]]
+assert automerge.get_pending_changes(withheld) == [hashes[2]]
+# dependencies supplied by other means than apply_changes unblock pending changes too
+automerge.merge(withheld, source)
+assert automerge.get_pending_changes(withheld) == [] and automerge.get_missing_deps(withheld) == []
+assert automerge.apply_changes(withheld, []).applied == 0
+assert automerge.get_missing_deps(replica, [bytes(32)]) == [bytes(32)]
+assert automerge.get_missing_deps(replica, [hashes[2]]) == []
+automerge.apply_changes(withheld, [out_of_order[1]])
+assert automerge.get_missing_deps(withheld) == [] and withheld.step == 2
+
+found = automerge.get_change_by_hash(source, hashes[1])
+assert found.bytes() == out_of_order[1].bytes()
+assert automerge.get_change_by_hash(source, bytes(32)) is None
+try:
+    automerge.get_change_by_hash(source, b"short")
+    assert False
+except ValueError:
+    pass
+
+first, second = out_of_order[0], out_of_order[1]
+assert second.hash == hashes[1] and second.deps == [hashes[0]] and first.deps == []
+assert second.actor_id == automerge.get_actor(source) and (first.seq, second.seq) == (1, 2)
+assert second.len == 1 and isinstance(second.timestamp, int) and second.message is None
+with automerge.transaction(source, "fix typo") as d:
+    d.step = 3
+assert automerge.get_last_local_change(source).message == "fix typo"
+
+reloaded_change = automerge.Change(second.bytes())
+assert reloaded_change == second and reloaded_change != first and second != second.bytes()
+assert len({second, reloaded_change, first}) == 2
+assert repr(automerge.get_last_local_change(source)) == f"Change(actor={automerge.get_actor(source).hex()}, seq=4, message=\"fix typo\")"
+
+expanded = automerge.get_last_local_change(source).decode()
+assert expanded.actor_id == automerge.get_actor(source) and expanded.seq == 4
+assert expanded.message == "fix typo" and expanded.deps == [hashes[2]] and expanded.extra_bytes == b""
+assert expanded.hash == automerge.get_last_local_change(source).hash
+assert expanded.start_op == 4 and isinstance(expanded.time, int)
+assert len(expanded.operations) == 1
+op = expanded.operations[0]
+assert op["action"] == "put" and op["object"] == "_root" and op["key"] == "step"
+assert op["value"] == 3 and op["insert"] is False and len(op["pred"]) == 1
+with automerge.transaction(source) as d:
+    d.lines = ["a"]
+list_ops = automerge.get_last_local_change(source).decode().operations
+# assigning a list of scalars inserts the values directly, without placeholders
+assert [op["action"] for op in list_ops] == ["make_list", "put"]
+assert list_ops[1]["object"].endswith(automerge.get_actor(source).hex())
+assert list_ops[1]["elem_id"] == "_head" and list_ops[1]["insert"] is True
+assert list_ops[1]["value"] == "a" and list_ops[1]["pred"] == []
+
+heads_before = automerge.get_heads(source)
+with automerge.transaction(source, "types \"quoted\"\n") as d:
+    d.blob = b"\x00\xffab"
+    d.total = automerge.Counter(2)
+    d.ratio = 0.5
+change_json = json.loads(automerge.get_last_local_change(source).to_json())
+assert change_json == json.loads(automerge.get_last_local_change(source).decode().to_json())
+assert change_json["actor_id"] == automerge.get_actor(source).hex()
+assert change_json["hash"] == automerge.get_last_local_change(source).hash.hex()
+assert change_json["deps"] == [h.hex() for h in heads_before]
+assert change_json["message"] == "types \"quoted\"\n" and change_json["extra_bytes"] == ""
+json_ops = {op["key"]: op for op in change_json["operations"]}
+assert json_ops["blob"]["action"] == "put" and json_ops["blob"]["datatype"] == "bytes"
+assert base64.b64decode(json_ops["blob"]["value"]) == b"\x00\xffab"
+assert json_ops["total"]["value"] == 2 and json_ops["total"]["datatype"] == "counter"
+assert json_ops["ratio"]["value"] == 0.5 and "datatype" not in json_ops["ratio"]
+assert automerge.get_last_local_change(source).to_json() == automerge.get_last_local_change(source).to_json()
+
+streamed = automerge.init()
+assert automerge.apply_changes(streamed, out_of_order[0]).applied == 1
+assert automerge.apply_changes(streamed, out_of_order[1].bytes()).applied == 1
+assert automerge.apply_changes(streamed, (c for c in automerge.get_changes(source, []))).applied == len(automerge.get_changes(source, [])) - 2
+assert automerge.get_heads(streamed) == automerge.get_heads(source)
+assert automerge.apply_changes(streamed, iter([])).applied == 0
+def broken_stream():
+    yield out_of_order[0]
+    yield out_of_order[1]
+    yield "not a change"
+broken = automerge.init()
+try:
+    automerge.apply_changes(broken, broken_stream())
+    assert False
+except TypeError as e:
+    assert "2 changes were applied before the error" in str(e)
+assert broken.step == 1
+
+left = automerge.init()
+with automerge.transaction(left) as d:
+    d.shared = "base"
+right = automerge.fork(left)
+with automerge.transaction(left) as d:
+    d.left = 1
+with automerge.transaction(right) as d:
+    d.right = 2
+with automerge.transaction(right) as d:
+    d.shared = "edited"
+to_right = automerge.get_changes_added(left, right)
+to_left = automerge.get_changes_added(right, left)
+assert len(to_right) == 1 and len(to_left) == 2
+automerge.apply_changes(right, to_right)
+automerge.apply_changes(left, to_left)
+assert sorted(automerge.get_heads(left)) == sorted(automerge.get_heads(right))
+assert (left.left, left.right, left.shared) == (1, 2, "edited")
+assert automerge.get_changes_added(left, right) == [] and automerge.get_changes_added(left, left) == []
+
+compared = automerge.init()
+with automerge.transaction(compared) as d:
+    d.config = {"name": "x", "tags": ["a", "b"], "nested": {"n": 1.5, "none": None}}
+    d.body = automerge.Text("hello")
+    d.hits = automerge.Counter(3)
+assert compared.config == {"name": "x", "tags": ["a", "b"], "nested": {"n": 1.5, "none": None}}
+assert compared.config != {"name": "x", "tags": ["a", "b"]}
+assert not (compared.config != {"name": "x", "tags": ["a", "b"], "nested": {"n": 1.5, "none": None}})
+assert compared.config.tags == ["a", "b"] and compared.config.tags != ["b", "a"] and compared.config.tags != ("a", "b")
+assert compared.body == "hello" and compared.body != "hell" and compared.body == automerge.Text("hello")
+assert compared == {"config": compared.config, "body": "hello", "hits": 3}
+compared_copy = automerge.fork(compared)
+assert compared_copy == compared and compared_copy.config.tags == compared.config.tags
+with automerge.transaction(compared_copy) as d:
+    d.config.tags.append("c")
+assert compared_copy != compared and compared_copy.config.tags[:2] == ["a", "b"]
+assert automerge.at(compared_copy, automerge.get_heads(compared)) == compared
+# other types get to compare themselves
+class EqualToAll:
+    def __eq__(self, other):
+        return True
+assert compared.config == EqualToAll() and compared.config.tags == EqualToAll()
+assert compared.config.__eq__(5) is NotImplemented and compared.config.tags.__eq__("ab") is NotImplemented
+assert compared.config != 5 and compared.config.tags != "ab"
+conflicting = automerge.fork(compared)
+with automerge.transaction(compared) as d:
+    d.config.name = "left"
+with automerge.transaction(conflicting) as d:
+    d.config.name = "right"
+automerge.merge(compared, conflicting)
+assert len(compared.config.get_all("name")) == 2
+assert compared.config == {"name": compared.config.name, "tags": ["a", "b"], "nested": {"n": 1.5, "none": None}}
+
+snapshot = compared.to_py()
+assert type(snapshot) is dict and type(snapshot["config"]["tags"]) is list
+assert snapshot["body"] == "hello" and type(snapshot["body"]) is str
+assert snapshot["hits"] == 3 and type(snapshot["hits"]) is int
+assert json.loads(json.dumps(snapshot)) == snapshot
+assert compared.config.tags.to_py() == ["a", "b"]
+old_heads = automerge.get_heads(compared)
+with automerge.transaction(compared) as d:
+    d.config.tags.append("c")
+    d.body = automerge.Text("bye")
+assert compared.to_py(old_heads) == snapshot and compared.config.tags.to_py(heads=old_heads) == ["a", "b"]
+assert compared.to_py()["body"] == "bye"
+
+initial = {"title": "t", "items": [1, {"x": None}], "body": automerge.Text("hi"), "hits": automerge.Counter(2)}
+prefilled = automerge.init(initial, "initial state")
+assigned = automerge.init()
+with automerge.transaction(assigned) as d:
+    for key, value in initial.items():
+        d[key] = value
+assert prefilled == assigned and prefilled.to_py() == {"title": "t", "items": [1, {"x": None}], "body": "hi", "hits": 2}
+assert automerge.get_last_local_change(prefilled).message == "initial state"
+assert len(automerge.get_changes(prefilled, [])) == 1
+assert automerge.get_heads(automerge.init()) == [] and automerge.get_heads(automerge.init(dict)) == []
+try:
+    automerge.init(["not", "a", "mapping"])
+    assert False
+except TypeError:
+    pass
+
+source_json = '{"name": "caf\\u00e9 \\ud83d\\ude00", "n": -3, "big": 18446744073709551615, "f": 1.5e3, "ok": true, "none": null, "list": [1, [2, {"x": "y"}], []], "obj": {"a": {}}}'
+from_json = automerge.load_json(source_json)
+assert from_json.to_py() == json.loads(source_json)
+assert len(automerge.get_changes(from_json, [])) == 1
+assert json.loads(automerge.to_json(from_json)) == json.loads(source_json)
+assert automerge.to_json(automerge.load_json(automerge.to_json(from_json))) == automerge.to_json(from_json)
+json_heads = automerge.get_heads(from_json)
+with automerge.transaction(from_json) as d:
+    d.body = automerge.Text("text \"quoted\"\n")
+    d.hits = automerge.Counter(4)
+assert json.loads(automerge.to_json(from_json))["body"] == "text \"quoted\"\n"
+assert json.loads(automerge.to_json(from_json))["hits"] == 4
+assert json.loads(automerge.to_json(from_json, json_heads)) == json.loads(source_json)
+with automerge.transaction(from_json) as d:
+    d.obj.a.blob = b"\x00"
+try:
+    automerge.to_json(from_json)
+    assert False
+except ValueError as e:
+    assert "obj.a.blob" in str(e)
+for invalid in ["", "[1]", "{", '{"a": 1,}', '{"a": 01x}', '{"a": "\\q"}', '{"a": 1} x', '{"a": 01}', '{"a": 1.}', '{"a": "\\u+041"}', '{"a": ' + "[" * 10000]:
+    try:
+        automerge.load_json(invalid)
+        assert False, invalid
+    except ValueError:
+        pass
+
+import copy
+
+original = automerge.init({"title": "a", "tags": ["x"], "meta": {"n": automerge.Counter(1)}})
+first_heads = automerge.get_heads(original)
+shallow = copy.copy(original)
+assert type(shallow) is type(original) and shallow == original
+with automerge.transaction(original) as d:
+    d.title = "b"
+assert shallow.title == "b" and copy.copy(original.tags).to_py() == ["x"]
+deep = copy.deepcopy(original)
+assert deep == original and automerge.get_actor(deep) != automerge.get_actor(original)
+with automerge.transaction(deep) as d:
+    d.title = "c"
+assert original.title == "b" and deep.title == "c"
+assert copy.deepcopy(original.meta) == {"n": 1} and type(copy.deepcopy(original.tags)) is list
+past = copy.deepcopy(automerge.at(original, first_heads))
+assert past.title == "a"
+with automerge.transaction(past) as d:
+    d.title = "from the past"
+assert original.title == "b"
+
+import io
+import os
+import pathlib
+import tempfile
+
+with tempfile.TemporaryDirectory() as tmp:
+    saved_path = os.path.join(tmp, "doc.automerge")
+    automerge.save_to_file(original, saved_path)
+    assert automerge.get_heads(automerge.load_from_file(saved_path)) == automerge.get_heads(original)
+    assert automerge.load_from_file(pathlib.Path(saved_path)) == original
+    try:
+        automerge.load_from_file(os.path.join(tmp, "missing"))
+        assert False
+    except FileNotFoundError as e:
+        assert e.filename == os.path.join(tmp, "missing")
+    try:
+        automerge.save_to_file(original, os.path.join(tmp, "missing", "doc"))
+        assert False
+    except OSError as e:
+        assert "missing" in str(e)
+buffer = io.BytesIO()
+automerge.save_to_file(original, buffer)
+assert buffer.getvalue() == automerge.save(original)
+buffer.seek(0)
+assert automerge.get_heads(automerge.load_from_file(buffer)) == automerge.get_heads(original)
+try:
+    automerge.load_from_file(io.StringIO("text"))
+    assert False
+except TypeError:
+    pass
+
+busy = automerge.init({"n": 0})
+peer = automerge.fork(busy)
+for i in range(200):
+    with automerge.transaction(peer) as d:
+        d.n = i
+caught_up = automerge.fork(busy)
+assert automerge.apply_changes(caught_up, (c for c in automerge.get_changes(peer, []))).applied == 199
+assert caught_up.n == 199
+assert automerge.merge(busy, peer)[0].key == "n" and busy.n == 199
+assert automerge.merge(busy, busy) == [] and automerge.merge(busy, copy.copy(busy)) == []
+try:
+    automerge.merge(busy, automerge.at(peer, automerge.get_heads(peer)))
+    assert False
+except ValueError:
+    pass
+
+import threading
+
+shared_reads = automerge.init({"items": list(range(100))})
+read_results = []
+def reader():
+    read_results.append(sum(shared_reads["items"]))
+readers = [threading.Thread(target=reader) for _ in range(8)]
+for t in readers:
+    t.start()
+for t in readers:
+    t.join()
+assert read_results == [4950] * 8
+
+rendered = automerge.init({"title": "before", "items": [1]})
+with automerge.transaction(rendered, snapshot=True) as d:
+    d.title = "during"
+    d["items"].append(2)
+    assert rendered.title == "before" and rendered["items"].to_py() == [1]
+    background = []
+    reader_thread = threading.Thread(target=lambda: background.append(rendered.to_py()))
+    reader_thread.start()
+    reader_thread.join()
+    assert background == [{"title": "before", "items": [1]}]
+    try:
+        automerge.transaction(rendered).__enter__()
+        assert False
+    except ValueError:
+        pass
+assert rendered.title == "during" and rendered["items"].to_py() == [1, 2]
+# without snapshot=True reading the document fails
+with automerge.transaction(rendered) as d:
+    d.title = "unreadable"
+    try:
+        rendered.title
+        assert False
+    except ValueError:
+        pass
+assert rendered.title == "unreadable"
+
+abandoned = automerge.init({"title": "kept"})
+with automerge.transaction(abandoned) as d:
+    d.title = "discarded"
+    d.rollback()
+    try:
+        d.rollback()
+        assert False
+    except ValueError:
+        pass
+    try:
+        d.title
+        assert False
+    except ValueError:
+        pass
+assert abandoned.title == "kept" and len(automerge.get_changes(abandoned, [])) == 1
+with automerge.transaction(abandoned) as d:
+    d.nested = {"a": 1}
+with automerge.transaction(abandoned) as d:
+    d.nested.a = 2
+    d.nested.rollback()
+assert abandoned.nested.a == 1
+with automerge.transaction(abandoned) as d:
+    d.title = "committed"
+try:
+    d.rollback()
+    assert False
+except ValueError:
+    pass
+assert abandoned.title == "committed"
+
+# commit() without a with block
+committed = automerge.init()
+tx = automerge.transaction(committed)
+tx.title = "explicit"
+head = tx.commit(message="set title", time=1000)
+assert [head] == automerge.get_heads(committed)
+assert committed.title == "explicit"
+change = automerge.get_changes(committed, [])[-1]
+assert change.message == "set title" and change.timestamp == 1000
+try:
+    tx.commit()
+    assert False
+except ValueError:
+    pass
+assert automerge.transaction(committed).commit() is None
+with automerge.transaction(committed, "from transaction") as d:
+    d.title = "inside with"
+    inner = d.commit()
+assert d.get_change().message == "from transaction"
+assert automerge.get_heads(committed) == [inner] and committed.title == "inside with"
+
+# commit time
+timed = automerge.init()
+with automerge.transaction(timed, time=1_700_000_000_123) as d:
+    d.a = 1
+assert d.get_change().timestamp == 1_700_000_000_123
+with automerge.transaction(timed, time=datetime.datetime(2024, 1, 2, 3, 4, 5, 6000, tzinfo=datetime.timezone.utc)) as d:
+    d.a = 2
+assert d.get_change().timestamp == automerge.timestamp_millis(datetime.datetime(2024, 1, 2, 3, 4, 5, 6000, tzinfo=datetime.timezone.utc))
+with automerge.transaction(timed, time=1500.0) as d:
+    d.a = 3
+assert d.get_change().timestamp == 1500
+tx = automerge.transaction(timed, time=10)
+tx.a = 4
+tx.commit(time=20)
+assert automerge.get_changes(timed, [])[-1].timestamp == 20
+for invalid in ["yesterday", float("nan"), datetime.datetime(2024, 1, 1)]:
+    try:
+        automerge.transaction(timed, time=invalid)
+        assert False
+    except (TypeError, ValueError):
+        pass
+
+# actor per transaction and for forks
+shared = automerge.init()
+automerge.set_actor(shared, b"\x01\x02")
+with automerge.transaction(shared, actor=b"\xaa\xbb") as d:
+    d.by = "alice"
+assert d.get_change().actor_id == b"\xaa\xbb"
+assert automerge.get_actor(shared) == b"\x01\x02"
+with automerge.transaction(shared, actor="ccdd") as d:
+    d.by = "bob"
+    d.rollback()
+assert automerge.get_actor(shared) == b"\x01\x02"
+with automerge.transaction(shared) as d:
+    d.by = "owner"
+assert d.get_change().actor_id == b"\x01\x02"
+try:
+    automerge.transaction(shared, actor="not hex")
+    assert False
+except ValueError:
+    pass
+forked = automerge.fork(shared, actor="0f0f")
+assert automerge.get_actor(forked) == b"\x0f\x0f"
+with automerge.transaction(forked) as d:
+    d.by = "fork"
+assert d.get_change().actor_id == b"\x0f\x0f"
+assert automerge.get_actor(automerge.fork_at(shared, automerge.get_heads(shared), actor=b"\x05")) == b"\x05"
+assert automerge.get_actor(automerge.fork(shared)) != b"\x01\x02"
+
+# transact
+ledger = automerge.init({"balance": 10})
+other = automerge.init({"seen": 0})
+
+def deposit(tx):
+    tx.balance = tx.balance + 5
+    with automerge.transaction(other) as o:
+        o.seen = 1
+    return ledger.balance
+
+result, head = automerge.transact(ledger, deposit, message="deposit", snapshot=True)
+assert result == 10 and ledger.balance == 15 and other.seen == 1
+assert automerge.get_heads(ledger) == [head]
+assert automerge.get_change_by_hash(ledger, head).message == "deposit"
+
+class Overdrawn(Exception):
+    pass
+
+def withdraw(tx):
+    tx.balance = tx.balance - 100
+    raise Overdrawn("balance would be negative")
+
+error = Overdrawn("unused")
+try:
+    automerge.transact(ledger, withdraw)
+    assert False
+except Overdrawn as e:
+    error = e
+assert type(error) is Overdrawn and str(error) == "balance would be negative"
+assert ledger.balance == 15 and automerge.get_heads(ledger) == [head]
+assert automerge.transact(ledger, lambda tx: None) == (None, None)
+
+def self_committing(tx):
+    tx.balance = 0
+    return tx.commit()
+
+result, head = automerge.transact(ledger, self_committing)
+assert result == head and ledger.balance == 0
+with automerge.transaction(ledger) as d:
+    d.balance = 1
+assert ledger.balance == 1
+
+# change_hash of a transaction
+audited = automerge.init()
+with automerge.transaction(audited) as d:
+    assert d.change_hash is None
+    d.entry = "first"
+assert d.change_hash == automerge.get_heads(audited)[0] == d.get_change().hash
+with automerge.transaction(audited) as d:
+    pass
+assert d.change_hash is None and d.get_change() is None
+with automerge.transaction(audited) as d:
+    d.inner = {"x": 1}
+    inner = d.inner
+assert inner.change_hash == d.change_hash == automerge.get_heads(audited)[0]
+
+# pending ops and patches of an open transaction
+pending = automerge.init({"title": "draft", "tags": ["a", "b", "c"], "meta": {"views": automerge.Counter(1)}, "body": automerge.Text("hello world")})
+with automerge.transaction(pending) as d:
+    assert d.pending_ops() == 0 and d.pending_patches() == []
+    d.title = "final"
+    d.tags[1] = "B"
+    d.tags.append("d")
+    d.meta.views.increment(2)
+    d.extra = {"nested": [1, 2]}
+    assert d.pending_ops() == 8
+    patches = [(p.action, p.path, p.key, p.value, p.length) for p in d.pending_patches()]
+    d.rollback()
+assert sorted(patches, key=repr) == sorted([
+    ("put", [], "title", "final", 0),
+    ("put", [], "extra", {"nested": [1, 2]}, 0),
+    ("put", ["tags"], 1, "B", 0),
+    ("insert", ["tags"], 3, ["d"], 0),
+    ("increment", ["meta"], "views", 2, 0),
+], key=repr), patches
+assert pending.title == "draft"
+with automerge.transaction(pending) as d:
+    d.body[6:11] = "there"
+    del d.tags[0]
+    del d["title"]
+    patches = [(p.action, p.path, p.key, p.value, p.length) for p in d.pending_patches()]
+    d.rollback()
+assert sorted(patches, key=repr) == sorted([
+    ("delete", [], "title", None, 1),
+    ("delete", ["tags"], 0, None, 1),
+    ("delete", ["body"], 6, None, 5),
+    ("splice_text", ["body"], 6, "there", 0),
+], key=repr), patches
+
+# proxies of the document inside an open transaction, and where the open transaction comes from
+tree = automerge.init({"settings": {"theme": "light"}, "items": [1, 2]})
+settings = tree.settings
+items = tree["items"]
+with automerge.transaction(tree, "change theme") as d:
+    d.view_of(settings).theme = "dark"
+    d.view_of(items).append(3)
+    assert d.view_of(tree).settings.theme == "dark"
+    try:
+        automerge.transaction(tree)
+        assert False
+    except ValueError as e:
+        assert "test.py:" in str(e) and "change theme" in str(e), str(e)
+    try:
+        d.view_of(automerge.init({"settings": {}}).settings)
+        assert False
+    except ValueError:
+        pass
+assert tree.settings.theme == "dark" and list(tree["items"]) == [1, 2, 3]
+with automerge.transaction(tree) as d:
+    d.view_of(settings).font = "mono"
+assert tree.settings.font == "mono"
+
+# waiting for the open transaction instead of failing
+import time
+
+waiting = automerge.init({"count": 0})
+tx = automerge.transaction(waiting, snapshot=False)
+try:
+    waiting.count
+    assert False
+except ValueError:
+    pass
+automerge.set_read_behavior(waiting, "wait", timeout=0.05)
+timeouts = []
+def read_with_timeout():
+    try:
+        waiting.count
+    except automerge.TransactionWaitTimeout as e:
+        timeouts.append(e)
+reader = threading.Thread(target=read_with_timeout)
+reader.start()
+reader.join(5)
+assert len(timeouts) == 1 and isinstance(timeouts[0], TimeoutError)
+# the thread owning the transaction would wait for itself, so it fails right away
+automerge.set_read_behavior(waiting, "wait")
+for action, error in [(lambda: waiting.count, automerge.TransactionInProgressError), (lambda: automerge.transaction(waiting), automerge.NestedTransactionError)]:
+    try:
+        action()
+        assert False
+    except error:
+        pass
+seen = []
+reader = threading.Thread(target=lambda: seen.append(waiting.count), daemon=True)
+automerge.set_read_behavior(waiting, "wait")
+reader.start()
+time.sleep(0.05)
+assert seen == []
+tx.count = 1
+tx.commit()
+reader.join(5)
+assert seen == [1]
+# saving and applying changes wait for the transaction as well
+other_changes = automerge.get_changes(automerge.init({"other": 1}), [])
+tx = automerge.transaction(waiting)
+writers = [
+    threading.Thread(target=lambda: seen.append(len(automerge.save(waiting))), daemon=True),
+    threading.Thread(target=lambda: seen.append(automerge.apply_changes(waiting, other_changes).applied), daemon=True),
+]
+for writer in writers:
+    writer.start()
+time.sleep(0.05)
+assert seen == [1]
+tx.count = 2
+tx.commit()
+for writer in writers:
+    writer.join(5)
+assert len(seen) == 3 and 1 in seen[1:] and waiting.count == 2 and waiting.other == 1
+# merging and opening transactions wait as well
+merged_changes = automerge.init({"merged": 1})
+tx = automerge.transaction(waiting)
+writers = [
+    threading.Thread(target=lambda: seen.append(len(automerge.merge(waiting, merged_changes))), daemon=True),
+    threading.Thread(target=lambda: seen.append(len(automerge.merge(merged_changes, waiting)))),
+    threading.Thread(target=lambda: automerge.transaction(waiting).commit(), daemon=True),
+]
+for writer in writers:
+    writer.start()
+time.sleep(0.05)
+assert len(seen) == 3
+tx.count = 3
+tx.commit()
+for writer in writers:
+    writer.join(5)
+assert len(seen) == 5 and waiting.count == 3 and waiting.merged == 1 and merged_changes.count == 3
+automerge.set_read_behavior(waiting, "fail")
+for behavior, timeout in [("sometimes", None), ("fail", 1.0), ("wait", -1.0), ("wait", float("nan"))]:
+    try:
+        automerge.set_read_behavior(waiting, behavior, timeout)
+        assert False
+    except ValueError:
+        pass
+
+# exception hierarchy
+assert issubclass(automerge.AutomergeBaseError, ValueError)
+for error in [automerge.TransactionInProgressError, automerge.TransactionReusedError, automerge.NestedTransactionError]:
+    assert issubclass(error, automerge.TransactionError) and issubclass(error, automerge.AutomergeBaseError)
+assert issubclass(automerge.LoadError, automerge.AutomergeBaseError)
+assert issubclass(automerge.HistoricalViewError, automerge.AutomergeBaseError)
+errors = automerge.init({"a": 1})
+tx = automerge.transaction(errors, snapshot=False)
+for action, error in [
+    (lambda: errors.a, automerge.TransactionInProgressError),
+    (lambda: automerge.transaction(errors), automerge.NestedTransactionError),
+]:
+    try:
+        action()
+        assert False
+    except error:
+        pass
+tx.commit()
+for action, error in [
+    (tx.commit, automerge.TransactionReusedError),
+    (lambda: automerge.load(b"not a document"), automerge.LoadError),
+    (lambda: automerge.transaction(automerge.at(errors, automerge.get_heads(errors))), automerge.HistoricalViewError),
+]:
+    try:
+        action()
+        assert False
+    except error:
+        pass
+
+# errors name the location in the document
+located = automerge.init({"items": [{"meta": {"tags": ["a"]}}]})
+try:
+    with automerge.transaction(located) as d:
+        d["items"][0].meta = {"tags": ["b", 1j]}
+    assert False
+except TypeError as e:
+    assert str(e) == "unsupported value of type complex at items[0].meta.tags[1]", str(e)
+try:
+    with automerge.transaction(located) as d:
+        d.imported = {"a": {"b": [0, {"my key": 2**70}]}}
+    assert False
+except OverflowError as e:
+    assert str(e).endswith(' at imported.a.b[1]["my key"]'), str(e)
+try:
+    with automerge.transaction(located) as d:
+        d.imported = {"a": {"b": {3: "c"}}}
+    assert False
+except TypeError as e:
+    assert str(e) == "keys must be str, not int at imported.a.b", str(e)
+try:
+    located["items"][0].meta.tags[5]
+    assert False
+except IndexError as e:
+    assert str(e).endswith("(in items[0].meta.tags)"), str(e)
+try:
+    located["items"][0].meta.missing
+    assert False
+except AttributeError as e:
+    assert str(e) == "missing (in items[0].meta)", str(e)
+assert "imported" not in located
+
+# proxies of deleted objects
+stale = automerge.init({"items": [{"name": "a"}, {"name": "b"}], "meta": {"tags": ["x"]}})
+items = stale["items"]
+second = items[1]
+tags = stale.meta.tags
+assert items.is_live() and second.is_live() and tags.is_live() and stale.is_live()
+with automerge.transaction(stale) as d:
+    del d["items"][1]
+    d.meta = {"tags": ["y"]}
+assert items.is_live() and len(items) == 1
+assert not second.is_live() and not tags.is_live()
+for action in [lambda: second.name, lambda: len(tags), lambda: tags[0], lambda: list(tags)]:
+    try:
+        action()
+        assert False
+    except automerge.StaleObjectError:
+        pass
+# views of the history still see the deleted objects
+assert automerge.at(stale, [automerge.get_changes(stale, [])[0].hash]).meta.tags[0] == "x"
+with automerge.transaction(stale) as d:
+    kept = d.view_of(items)
+    first = kept[0]
+    del d["items"]
+    assert not first.is_live() and not kept.is_live()
+    try:
+        first.name = "c"
+        assert False
+    except automerge.StaleObjectError:
+        pass
+assert "items" not in stale
+
+# path of a proxy
+located = automerge.init({"items": [{"meta": {"body": automerge.Text("hi")}}, {"meta": {}}]})
+meta = located["items"][1].meta
+assert located.path() == [] and located["items"].path() == ["items"]
+assert meta.path() == ["items", 1, "meta"]
+with automerge.transaction(located) as d:
+    del d["items"][0]
+    assert d.view_of(meta).path() == ["items", 0, "meta"]
+    d["items"].append({"meta": {"body": automerge.Text("new")}})
+    assert d["items"][1].meta.body.path() == ["items", 1, "meta", "body"]
+assert meta.path() == ["items", 0, "meta"]
+with automerge.transaction(located) as d:
+    del d["items"]
+try:
+    meta.path()
+    assert False
+except automerge.StaleObjectError:
+    pass
+
+# object ids of proxies
+identified = automerge.init()
+automerge.set_actor(identified, b"\xab")
+with automerge.transaction(identified) as d:
+    d.config = {"name": "x"}
+    assert d.obj_id == "_root" and d.config.obj_id == "1@ab"
+assert identified.obj_id == "_root"
+assert identified.config.obj_id == identified["config"].obj_id == "1@ab"
+operations = d.get_change().decode().operations
+assert [op["object"] for op in operations] == ["_root", "1@ab"]
+assert automerge.at(identified, automerge.get_heads(identified)).config.obj_id == "1@ab"
+# proxies hash by the object they point to, so they can key dicts, equal copies in forks are different keys
+labels = {identified: "root", identified.config: "config"}
+assert labels[identified["config"]] == "config" and labels[identified] == "root"
+forked = automerge.fork(identified)
+assert forked.config == identified.config and forked.config not in labels
+assert hash(automerge.init({"l": []}).l) != hash(automerge.init({"l": []}).l)
+
+# object types of proxies
+typed = automerge.init({"m": {}, "l": [], "t": automerge.Text("x")})
+assert typed.object_type == "map" and typed.m.object_type == "map"
+assert typed.l.object_type == "list" and isinstance(typed.l, automerge.Sequence)
+assert isinstance(typed.m, automerge.Mapping) and isinstance(typed, automerge.Document)
+with automerge.transaction(typed) as d:
+    assert d.object_type == "map" and d.l.object_type == "list" and d.t.object_type == "text"
+    assert isinstance(d.l, automerge.SequenceTransaction) and isinstance(d.m, automerge.MappingTransaction)
+    assert isinstance(d.t, automerge.TextTransaction) and isinstance(d.t, automerge.DocumentTransaction)
+
+# text is read live
+live = automerge.init({"body": automerge.Text("hello world")})
+body = live.body
+assert isinstance(body, automerge.TextView) and body == "hello world" and len(body) == 11
+with automerge.transaction(live) as d:
+    d.body[0:5] = "HELLO"
+assert str(body) == "HELLO world" and body == live.body
+assert body[0] == "H" and body[-1] == "d" and body[6:] == "world" and body[::-2] == "HELLO world"[::-2]
+try:
+    body[11]
+    assert False
+except IndexError as e:
+    assert "11" in str(e)
+assert repr(body) == "TextView('HELLO world')"
+old = automerge.at(live, [automerge.get_changes(live, [])[0].hash]).body
+assert old == "hello world" and old[:5] == "hello" and len(old) == 11
+assert body.path() == ["body"] and body.object_type == "text" and body.is_live()
+with automerge.transaction(live, snapshot=True) as d:
+    d.copy = body
+assert live.copy == "HELLO world" and live.copy.obj_id != body.obj_id
+assert live.to_py() == {"body": "HELLO world", "copy": "HELLO world"}
+with automerge.transaction(live) as d:
+    del d["body"]
+assert not body.is_live()
+# a text read with doc["body"] shows text spliced in later, instead of a stale copy
+spliced = automerge.init({"body": automerge.Text("Guten Tag")})
+t = spliced["body"]
+with automerge.transaction(spliced) as d:
+    d["body"][6:9] = "Morgen"
+    d["body"].insert(0, "»")
+assert str(t) == "»Guten Morgen" and t == spliced["body"]
+
+# text behaves like a str
+phrase = automerge.init({"body": automerge.Text("hello world"), "other": automerge.Text("hello world")})
+assert phrase["body"] == "hello world" and "hello world" == phrase["body"] and phrase["body"] != "hello"
+assert phrase["body"] == phrase["other"] and phrase["body"] == automerge.Text("hello world")
+assert automerge.Text("hello world") == phrase["body"]
+assert "lo wo" in phrase["body"] and "xyz" not in phrase["body"] and automerge.Text("world") in phrase["body"]
+assert phrase["body"].startswith("hello") and phrase["body"].startswith(("x", "h"))
+assert phrase["body"].endswith("world") and not phrase["body"].endswith("hello")
+assert phrase["body"].find("o") == 4 and phrase["body"].find("o", 5) == 7 and phrase["body"].find("z") == -1
+assert hash(automerge.Text("hi")) == hash("hi") and {automerge.Text("hi"): 1}["hi"] == 1
+for action in [lambda: hash(phrase["body"]), lambda: 1 in phrase["body"]]:
+    try:
+        action()
+        assert False
+    except TypeError:
+        pass
+
+# slicing text
+sliced = automerge.init({"body": automerge.Text("zwölf Äpfel 🍎!")})
+for text in [sliced.body, automerge.Text("zwölf Äpfel 🍎!")]:
+    plain = "zwölf Äpfel 🍎!"
+    assert len(text) == len(plain)
+    for index in [0, 3, -1, -2, len(plain) - 1, -len(plain)]:
+        assert text[index] == plain[index]
+    for piece in [slice(2, 8), slice(-3, None), slice(None, None, -1), slice(1, 100, 3), slice(100, 200), slice(-100, 2)]:
+        assert text[piece] == plain[piece], (piece, text[piece])
+    for index in [len(plain), -len(plain) - 1]:
+        try:
+            text[index]
+            assert False
+        except IndexError as e:
+            assert str(len(plain)) in str(e)
+# indices from slices line up with edits
+start = sliced.body.find("🍎")
+with automerge.transaction(sliced) as d:
+    d.body[start:start + 1] = "🍏"
+assert sliced.body == "zwölf Äpfel 🍏!"
+
+# negative indices in text transactions
+negative = automerge.init({"body": automerge.Text("grüß 🌍")})
+with automerge.transaction(negative) as d:
+    assert d.body[-1] == "🌍"
+    assert d.body[-2] == " "
+    assert d.body[-4] == "ü"
+    assert d.body[-6] == "g"
+    d.body[-1] = "🌎"
+    d.body[-4] = "ö"
+assert negative.body == "größ 🌎"
+with automerge.transaction(negative) as d:
+    del d.body[-1]
+    del d.body[-2]
+assert negative.body == "grö "
+with automerge.transaction(negative) as d:
+    for index in [4, -5]:
+        for action in [lambda: d.body[index], lambda: d.body.__setitem__(index, "x"), lambda: d.body.__delitem__(index)]:
+            try:
+                action()
+                assert False
+            except IndexError as e:
+                assert "length 4" in str(e)
+assert negative.body == "grö "
+
+# deleting slices of text
+plain = "ab€cd😀ef"
+for piece in [slice(2, 5), slice(-3, None), slice(None, -2), slice(4, 2), slice(-100, 100), slice(None, None, 2), slice(None, None, -3), slice(6, 1, -2), slice(100, 200)]:
+    shrinking = automerge.init({"body": automerge.Text(plain)})
+    with automerge.transaction(shrinking) as d:
+        del d.body[piece]
+    expected = list(plain)
+    del expected[piece]
+    assert shrinking.body == "".join(expected), (piece, str(shrinking.body))
+
+# inserting and appending text
+built = automerge.init({"body": automerge.Text("")})
+with automerge.transaction(built) as d:
+    d.body.append("Grüße")
+    d.body.append("")
+    d.body.append(" 👋")
+    d.body.insert(0, "¡")
+    d.body.insert(-2, ",")
+    d.body.insert(len(d.body), "!")
+    d.body.insert(-1, "🌍")
+assert built.body == "¡Grüße, 👋🌍!", str(built.body)
+with automerge.transaction(built) as d:
+    for index in [len(d.body) + 1, -len(d.body) - 1]:
+        try:
+            d.body.insert(index, "x")
+            assert False
+        except IndexError:
+            pass
+assert built.body == "¡Grüße, 👋🌍!"
+
+# updating text with a diff
+base = automerge.init({"body": automerge.Text("Der Bär läuft über die Straße.")})
+left = automerge.fork(base)
+right = automerge.fork(base)
+with automerge.transaction(left) as d:
+    d.body.update("Der braune Bär läuft über die Straße.")
+with automerge.transaction(right) as d:
+    d.body.update("Der Bär läuft über die große Straße!")
+with automerge.transaction(right) as d:
+    assert d.body.pending_ops() == 0
+    d.body.update("Der Bär läuft über die große Straße!")
+    assert d.body.pending_ops() == 0
+automerge.merge(left, right)
+assert left.body == "Der braune Bär läuft über die große Straße!", str(left.body)
+
+# marks on text
+marked = automerge.init({"body": automerge.Text("schöne Grüße aus Köln")})
+with automerge.transaction(marked) as d:
+    d.body.mark(7, 12, "bold", True)
+    d.body.mark(-4, len(d.body), "link", "https://köln.de", expand="none")
+    d.body.mark(0, 6, "comment", 3, expand="after")
+assert sorted(marked.body.marks()) == [(0, 6, "comment", 3), (7, 12, "bold", True), (17, 21, "link", "https://köln.de")]
+left = automerge.fork(marked)
+right = automerge.fork(marked)
+with automerge.transaction(left) as d:
+    d.body.insert(9, "üü")
+with automerge.transaction(right) as d:
+    d.body.insert(12, "!!")
+    d.body.insert(7, "»")
+    d.body.insert(-4, "«")
+    d.body.append(".")
+    d.body.insert(0, ">")
+automerge.merge(left, right)
+assert left.body == ">schöne »Grüüüße!! aus «Köln."
+assert sorted(left.body.marks()) == [(1, 7, "comment", 3), (8, 18, "bold", True), (24, 28, "link", "https://köln.de")]
+with automerge.transaction(left) as d:
+    d.body.unmark(8, 12, "bold")
+    for args, error in [((5, 2, "bold", True), ValueError), ((0, 100, "bold", True), IndexError), ((0, 1, "bold", [1]), TypeError)]:
+        try:
+            d.body.mark(*args)
+            assert False
+        except error:
+            pass
+    try:
+        d.body.mark(0, 1, "bold", True, expand="sideways")
+        assert False
+    except ValueError as e:
+        assert "sideways" in str(e)
+assert sorted(left.body.marks()) == [(1, 7, "comment", 3), (12, 18, "bold", True), (24, 28, "link", "https://köln.de")]
+
+# blocks and spans of rich text
+structured = automerge.init({"body": automerge.Text("Titel\nerster Absatz")})
+with automerge.transaction(structured) as d:
+    d.body.split_block(0, {"type": "heading", "level": 1, "parents": []})
+    d.body.split_block(7, {"type": "paragraph"})
+    d.body.mark(8, 13, "bold", True)
+    assert [type(span) for span in d.body.spans()] == [automerge.BlockMarker, automerge.TextSpan] * 2 + [automerge.TextSpan]
+    try:
+        d.body.split_block(100)
+        assert False
+    except IndexError:
+        pass
+assert len(structured.body) == 21 and str(structured.body) == "\ufffcTitel\n\ufffcerster Absatz"
+spans = structured.body.spans()
+assert [span.attrs for span in spans[::2][:2]] == [{"type": "heading", "level": 1, "parents": []}, {"type": "paragraph"}]
+assert [(span.text, span.marks) for span in spans[1::2]] == [("Titel\n", {}), ("erste", {"bold": True})]
+assert (spans[4].text, spans[4].marks) == ("r Absatz", {})
+assert repr(spans[2]) == "BlockMarker({'type': 'paragraph'})" and repr(spans[4]) == "TextSpan('r Absatz', marks={})"
+before = automerge.at(structured, automerge.get_heads(structured))
+with automerge.transaction(structured) as d:
+    d.body.update_block(0, {"type": "paragraph"})
+    d.body.join_block(-14)
+spans = structured.body.spans()
+assert spans[0].attrs == {"type": "paragraph"} and [span.text for span in spans[1:]] == ["Titel\n", "erste", "r Absatz"]
+assert len(before.body.spans()) == 5
+
+# cursors into text
+pointed = automerge.init({"body": automerge.Text("我们 lesen Bücher")})
+cursor = pointed.body.cursor(-6)
+assert pointed.body[pointed.body.cursor_position(cursor)] == "B"
+assert automerge.Cursor(str(cursor)) == cursor
+assert automerge.Cursor(bytes(cursor)) == cursor
+assert hash(automerge.Cursor(bytes(cursor))) == hash(cursor)
+assert repr(cursor) == f"Cursor('{cursor}')"
+for junk in ["junk", b"\xff"]:
+    try:
+        automerge.Cursor(junk)
+        assert False
+    except ValueError:
+        pass
+before = automerge.get_heads(pointed)
+remote = automerge.fork(pointed)
+with automerge.transaction(remote) as d:
+    d.body.insert(0, "🙂 ")
+    d.body.insert(5, "gern ")
+with automerge.transaction(pointed) as d:
+    d.body.append(".")
+automerge.merge(pointed, remote)
+assert pointed.body == "🙂 我们 gern lesen Bücher."
+assert pointed.body.cursor_position(cursor) == 16
+assert pointed.body.cursor_position(cursor, before) == 9
+assert automerge.at(pointed, before).body.cursor_position(cursor) == 9
+reloaded = automerge.load(automerge.save(pointed))
+assert reloaded.body.cursor_position(automerge.Cursor(str(cursor))) == 16
+try:
+    pointed.body.cursor(len(pointed.body))
+    assert False
+except IndexError:
+    pass
+
+# cursors into lists
+rows = automerge.init({"rows": ["a", "b", "c", "d"]})
+selected = rows.rows.cursor(2)
+last = rows.rows.cursor(-1)
+stashed = str(selected)
+remote = automerge.fork(rows)
+with automerge.transaction(remote) as d:
+    d.rows[0:0] = ["new", "newer"]
+automerge.merge(rows, remote)
+assert rows.rows[rows.rows.cursor_position(automerge.Cursor(stashed))] == "c"
+assert rows.rows.cursor_position(selected) == 4
+assert rows.rows.cursor_position(selected, automerge.get_heads(remote)) == 4
+with automerge.transaction(rows) as d:
+    assert d.rows.cursor_position(selected) == 4
+    assert d.rows.cursor(4) == selected
+    del d.rows[4]
+    # deleted elements resolve to the index of the next element still there
+    assert d.rows.cursor_position(selected) == 4
+    del d.rows[4]
+    assert d.rows.cursor_position(last) == len(d.rows)
+    try:
+        d.rows.cursor(len(d.rows))
+        assert False
+    except IndexError as e:
+        assert "list of length 4" in str(e)
+assert rows.rows.cursor_position(selected) == 4
+
+# text indices are unicode code points, like python str indices
+plain = "漢字😀é🇩🇪 ok"
+points = automerge.init({"body": automerge.Text(plain)})
+assert len(points.body) == len(plain) == 10
+assert [points.body[i] for i in range(len(plain))] == list(plain)
+with automerge.transaction(points) as d:
+    assert len(d.body) == len(plain)
+    assert [d.body[i] for i in range(len(plain))] == list(plain)
+    # replacing a single character never removes more than that character
+    # (back to front, so the indices of the later edits are the same as in plain)
+    d.body[-1] = "K!"
+    d.body[5] = ""
+    d.body[2] = "🙂🙃"
+    d.body[0] = "ä"
+expected = list(plain)
+expected[2] = "🙂🙃"
+expected[-1] = "K!"
+expected[0] = "ä"
+expected[5] = ""
+assert points.body == "".join(expected), ascii(str(points.body))
+plain = str(points.body)
+with automerge.transaction(points) as d:
+    d.body[1:3] = "字😎"
+    d.body[::4] = "ABC"
+    del d.body[-4:-2]
+    d.body.insert(3, "中文")
+expected = list(plain)
+expected[1:3] = "字😎"
+expected[::4] = "ABC"
+del expected[-4:-2]
+expected[3:3] = "中文"
+assert points.body == "".join(expected), ascii(str(points.body))
+
+# splicing lists
+table = automerge.init({"rows": [1, 2, 3, 4, 5]})
+with automerge.transaction(table) as d:
+    d.rows.splice(1, 2, ["a", "b", "c"])
+    # one op per deleted and per inserted element, no placeholders
+    assert d.rows.pending_ops() == 5
+    d.rows.splice(-1, 10, [])
+    d.rows.splice(len(d.rows), 0, [True, {"nested": [1.5, None]}, ["x"], automerge.Text("tx"), 7])
+    d.rows.splice(-len(d.rows), 1, (i * i for i in range(3)))
+    for index in [len(d.rows) + 1, -len(d.rows) - 1]:
+        try:
+            d.rows.splice(index, 0, [1])
+            assert False
+        except IndexError as e:
+            assert f"list of length {len(d.rows)}" in str(e)
+assert dump(table.rows) == [0, 1, 4, "a", "b", "c", 4, True, {"nested": [1.5, None]}, ["x"], "tx", 7], dump(table.rows)
+try:
+    with automerge.transaction(table) as d:
+        d.rows.splice(0, 0, [1, 2**70])
+    assert False
+except OverflowError as e:
+    assert str(e).endswith(" at rows[1]"), str(e)
+assert len(table.rows) == 12
+
+# lists of scalars are written with a single splice
+import time
+floats = [i / 3 for i in range(50_000)]
+started = time.perf_counter()
+with automerge.transaction(table) as d:
+    d.floats = floats
+    d.mixed = [1, "two", [3], {"four": 4}, 5.0, 6]
+elapsed = time.perf_counter() - started
+assert table.floats == floats and dump(table.mixed) == [1, "two", [3], {"four": 4}, 5.0, 6]
+ops = automerge.get_last_local_change(table).decode().operations
+# one op to create each list and one insert per element (plus the contents of the nested objects),
+# nothing is overwritten
+assert len(ops) == 1 + 50_000 + 1 + 6 + 2, len(ops)
+assert all(op["insert"] for op in ops if op["action"] == "put" and "elem_id" in op)
+assert all(op["pred"] == [] for op in ops)
+print(f"writing a list of 50000 floats took {elapsed:.2f}s")
+
+# new list elements are inserted directly, without Null placeholders
+fresh = automerge.init({"rows": [0]})
+with automerge.transaction(fresh) as d:
+    d.rows.append(1)
+    d.rows.append({"cells": [2, 3]})
+    d.rows[len(d.rows)] = ["four"]
+    d.rows[1:2] = [1.5, automerge.Text("x"), None]
+    d.rows[:0] = []
+assert dump(fresh.rows) == [0, 1.5, "x", None, {"cells": [2, 3]}, ["four"]], dump(fresh.rows)
+ops = automerge.get_last_local_change(fresh).decode().operations
+# every element is created by a single insert, the only overwritten or deleted element is the replaced 1
+assert [op["action"] for op in ops if op["pred"]] == ["delete"]
+assert not any(op["action"] == "put" and op.get("value", 0) is None and not op["insert"] for op in ops)
+# appended 1, appended map + 2 cells, ["four"] + its element, 3 slice elements + the character of the text
+assert len([op for op in ops if op["insert"]]) == 1 + 3 + 2 + 4
+
+# reading lists in bulk
+bulk = automerge.init({"numbers": list(range(10)) + [{"a": [1]}, automerge.Counter(3), automerge.Text("t")]})
+plain = list(range(10))
+assert bulk.numbers.to_list()[:10] == plain
+for start, stop in [(None, None), (2, 5), (-3, None), (None, -10), (8, 100), (5, 2), (-100, 3)]:
+    assert bulk.numbers.to_list(start, stop, deep=True) == (plain + [{"a": [1]}, 3, "t"])[start:stop], (start, stop)
+    assert bulk.numbers[start:stop] == bulk.numbers.to_list(start, stop)
+shallow = bulk.numbers.to_list(10)
+assert isinstance(shallow[0], automerge.Mapping) and shallow[0].a == [1]
+assert isinstance(shallow[1], automerge.Counter) and shallow[1] == 3
+assert isinstance(shallow[2], automerge.TextView) and shallow[2] == "t"
+deep = bulk.numbers.to_list(10, deep=True)
+assert deep == [{"a": [1]}, 3, "t"] and type(deep[0]) is dict and type(deep[1]) is int and type(deep[2]) is str
+old = automerge.get_heads(bulk)
+with automerge.transaction(bulk) as d:
+    d.numbers[0:2] = ["x"]
+assert bulk.numbers.to_list(0, 3) == ["x", 2, 3]
+assert automerge.at(bulk, old).numbers.to_list(0, 3) == [0, 1, 2]
+assert automerge.at(bulk, old).numbers[0:3] == [0, 1, 2]
+
+# range queries
+ranged = automerge.init({"big": {f"k{i:06}": i for i in range(20_000)}, "seq": list(range(10))})
+window = ranged.big.range("k010000", "k010100")
+assert window.__length_hint__() == 100
+pairs = list(window)
+assert pairs == [(f"k{i:06}", i) for i in range(10_000, 10_100)]
+assert list(ranged.big.range("k019998")) == [("k019998", 19998), ("k019999", 19999)]
+assert [key for key, _ in ranged.big.range(None, "k000003")] == ["k000000", "k000001", "k000002"]
+assert list(ranged.big.range("x")) == [] and list(ranged.big.range("k000005", "k000005")) == []
+keys = iter(ranged.big)
+assert keys.__length_hint__() == 20_000 and next(keys) == "k000000" and keys.__length_hint__() == 19_999
+assert list(ranged.seq.range(3, 6)) == [(3, 3), (4, 4), (5, 5)]
+assert list(ranged.seq.range(-2)) == [(8, 8), (9, 9)] and list(ranged.seq.range(None, -8)) == [(0, 0), (1, 1)]
+assert ranged.seq.range(2, 5).__length_hint__() == 3
+assert dict(ranged.big.items())["k000042"] == 42 and sum(ranged.big.values()) == sum(range(20_000))
+before = automerge.get_heads(ranged)
+with automerge.transaction(ranged) as d:
+    d.big["k010050"] = "changed"
+    d.big["k010050a"] = automerge.Counter(1)
+assert dict(ranged.big.range("k010050", "k010051")) == {"k010050": "changed", "k010050a": 1}
+ranged.big.range("k010050a", "k010051").__next__()[1].increment(2)
+assert ranged.big["k010050a"] == 3
+assert dict(automerge.at(ranged, before).big.range("k010050", "k010051")) == {"k010050": 10050}
+
+# proxies are copied with all their content
+source = automerge.init({"template": {"title": automerge.Text("hi"), "hits": automerge.Counter(2), "tags": ["a", {"b": [1]}]}})
+target = automerge.init({})
+with automerge.transaction(target) as d:
+    d.copy = source.template
+    d.tags = source.template.tags
+    d.title = source.template.title
+    d.nested = [source.template.tags, {"t": source.template.title}]
+assert dump(target.copy) == {"title": "hi", "hits": 2, "tags": ["a", {"b": [1]}]}, dump(target.copy)
+assert isinstance(target.copy.hits, automerge.Counter) and isinstance(target.copy.title, automerge.TextView)
+assert target.tags == ["a", {"b": [1]}] and target.title == "hi" and target.nested == [["a", {"b": [1]}], {"t": "hi"}]
+# copies are independent of their source
+target.copy.hits.increment(1)
+assert target.copy.hits == 3 and source.template.hits == 2
+old = automerge.get_heads(source)
+with automerge.transaction(source, snapshot=True) as d:
+    d.template.tags.append("c")
+    # reading the document itself sees the snapshot from before the transaction
+    d.before = source.template.tags
+    d.after = d.template.tags
+    d.inside = [d.template.tags, {"again": d.template}]
+    d.template.self = d.template
+    d.text = d.template.title
+assert source.before == ["a", {"b": [1]}] and source.after == ["a", {"b": [1]}, "c"]
+assert source.inside == [["a", {"b": [1]}, "c"], {"again": {"title": "hi", "hits": 2, "tags": ["a", {"b": [1]}, "c"]}}]
+assert dump(source.template.self) == {"title": "hi", "hits": 2, "tags": ["a", {"b": [1]}, "c"]}
+assert source.text == "hi"
+with automerge.transaction(target) as d:
+    d.historical = automerge.at(source, old).template.tags
+assert target.historical == ["a", {"b": [1]}]
+# without a snapshot the document can't be read during its transaction, this fails instead of waiting forever
+template = source.template
+automerge.set_read_behavior(source, "wait")
+with automerge.transaction(source, snapshot=False) as d:
+    try:
+        d.broken = template
+        assert False
+    except automerge.TransactionInProgressError:
+        pass
+    d.fine = d.template.tags
+automerge.set_read_behavior(source, "fail")
+assert source.fine == ["a", {"b": [1]}, "c"] and "broken" not in source
+# proxies of a transaction another thread is writing to wait for it instead of being read as the own transaction
+busy = automerge.init({"items": [1]})
+busy_tx = automerge.transaction(busy)
+busy_items = busy_tx["items"]
+extending = threading.Event()
+
+def slow_items():
+    extending.set()
+    time.sleep(0.2)
+    yield 2
+
+extender = threading.Thread(target=lambda: busy_tx["items"].splice(1, 0, slow_items()))
+extender.start()
+extending.wait()
+with automerge.transaction(target) as d:
+    d.busy = busy_items
+extender.join()
+busy_tx.commit()
+assert target.busy == [1, 2] and busy["items"] == [1, 2]
+
+# tuples and sets are written as lists
+shapes = automerge.init({"point": (1.0, 2.0), "shape": {"corners": ((0, 0), (1, (2, 3))), "tags": {"b", "c", "a"}}})
+assert shapes.to_py() == {"point": [1.0, 2.0], "shape": {"corners": [[0, 0], [1, [2, 3]]], "tags": ["a", "b", "c"]}}
+with automerge.transaction(shapes) as d:
+    d.point = (3, (4, {"z": (5,)}))
+    d.frozen = frozenset([3, 1, 2])
+    d.shape.corners[0:1] = ((9, 9), {"x"})
+    d.label = "ab"
+    d.raw = b"ab"
+assert shapes.point == [3, [4, {"z": [5]}]] and shapes.frozen == [1, 2, 3]
+assert shapes.shape.corners == [[9, 9], ["x"], [1, [2, 3]]]
+assert shapes.label == "ab" and shapes.raw == b"ab"
+try:
+    with automerge.transaction(shapes) as d:
+        d.mixed = {"x": {1, "a"}}
+    assert False
+except TypeError as e:
+    assert "sorted" in str(e) and "mixed.x" in str(e), e
+assert "mixed" not in shapes
+
+# Text nested anywhere inside a value creates a text object
+nested_text = automerge.init({})
+with automerge.transaction(nested_text) as d:
+    d["doc"] = {"title": "x", "body": automerge.Text("hello"), "parts": [automerge.Text("a"), {"deep": (automerge.Text("b"),)}]}
+ops = automerge.get_last_local_change(nested_text).decode().operations
+assert [op["action"] for op in ops].count("make_text") == 3
+body = nested_text.doc.body
+assert isinstance(body, automerge.TextView) and body == "hello" and type(nested_text.doc.title) is str
+assert isinstance(nested_text.doc.parts[0], automerge.TextView) and nested_text.doc.parts[0] == "a"
+assert isinstance(nested_text.doc.parts[1].deep[0], automerge.TextView) and nested_text.doc.parts[1].deep[0] == "b"
+with automerge.transaction(nested_text) as d:
+    d.doc.body.insert(5, " world")
+assert str(body) == "hello world"
+assert nested_text.to_py() == {"doc": {"title": "x", "body": "hello world", "parts": ["a", {"deep": ["b"]}]}}
+
+# local changes since the last upload
+outgoing = automerge.init({"n": 0})
+peer = automerge.fork(outgoing)
+uploaded = automerge.get_heads(outgoing)
+assert automerge.get_local_changes(outgoing, uploaded) == []
+for i in range(3):
+    with automerge.transaction(outgoing) as d:
+        d.n = i + 1
+with automerge.transaction(peer) as d:
+    d.peer = True
+automerge.merge(outgoing, peer)
+with automerge.transaction(outgoing) as d:
+    d.n = 4
+local = automerge.get_local_changes(outgoing, uploaded)
+assert [change.seq for change in local] == [2, 3, 4, 5]
+assert all(change.actor_id == automerge.get_actor(outgoing) for change in local)
+assert [change.hash for change in local] == [
+    change.hash for change in automerge.get_changes(outgoing, uploaded) if change.actor_id != automerge.get_actor(peer)
+]
+assert local[-1].hash == automerge.get_last_local_change(outgoing).hash
+assert automerge.get_local_changes(outgoing, automerge.get_heads(outgoing)) == []
+assert len(automerge.get_local_changes(outgoing, [])) == 5
+assert [change.seq for change in automerge.get_local_changes(peer, [])] == [1]
+
+# change hashes as hex
+hashed = automerge.init({"v": 1})
+with automerge.transaction(hashed) as d:
+    d.v = 2
+head = automerge.get_heads(hashed)[0]
+assert isinstance(head, automerge.ChangeHash) and len(str(head)) == 64 and str(head) == bytes(head).hex() == head.hex()
+assert repr(head) == f"ChangeHash({str(head)[:7]})"
+assert automerge.ChangeHash(str(head)) == head == automerge.ChangeHash(bytes(head)) == automerge.ChangeHash(head)
+assert head == bytes(head) and hash(head) == hash(bytes(head)) and len({head, automerge.ChangeHash(str(head))}) == 1
+change = automerge.get_last_local_change(hashed)
+assert change.hash == head and change.decode().hash == head and isinstance(change.deps[0], automerge.ChangeHash)
+first = change.deps[0]
+assert sorted([head, first]) == sorted([bytes(head), bytes(first)])
+for heads in ([first], [str(first)], [bytes(first)]):
+    assert automerge.at(hashed, heads).v == 1
+    assert automerge.fork_at(hashed, heads).v == 1
+    assert [c.hash for c in automerge.get_changes(hashed, heads)] == [head]
+assert automerge.get_change_by_hash(hashed, str(head)).hash == head
+for invalid in ["xyz", "00" * 31, b"\x00"]:
+    try:
+        automerge.ChangeHash(invalid)
+        assert False
+    except ValueError:
+        pass
+    try:
+        automerge.get_changes(hashed, [invalid])
+        assert False
+    except ValueError:
+        pass
+try:
+    automerge.ChangeHash(5)
+    assert False
+except TypeError:
+    pass
+with automerge.transaction(hashed) as d:
+    d.v = 3
+assert d.change_hash == automerge.get_heads(hashed)[0] and isinstance(d.change_hash, automerge.ChangeHash)
+
+# change hashes are ordered and work in sets
+peer_a = automerge.init({"x": 0})
+peer_b = automerge.fork(peer_a)
+with automerge.transaction(peer_a) as d:
+    d.a = 1
+with automerge.transaction(peer_b) as d:
+    d.b = 1
+automerge.merge(peer_a, peer_b)
+heads = automerge.get_heads(peer_a)
+assert len(heads) == 2 and sorted(heads) == sorted(heads, key=bytes) and sorted(heads) == sorted(reversed(heads))
+assert min(heads) < max(heads) and min(heads) <= bytes(min(heads)) and not max(heads) < min(heads)
+assert {*heads, *automerge.get_heads(peer_a), automerge.ChangeHash(str(heads[0]))} == set(heads)
+assert set(heads) - set(automerge.get_last_local_change(peer_a).deps) == set(heads)
+state_a, state_b = automerge.SyncState(), automerge.SyncState()
+assert state_a.their_heads is None and state_a.shared_heads == []
+while True:
+    msg_a = automerge.generate_sync_message(peer_a, state_a)
+    if msg_a is not None:
+        automerge.receive_sync_message(peer_b, state_b, msg_a)
+    msg_b = automerge.generate_sync_message(peer_b, state_b)
+    if msg_b is not None:
+        automerge.receive_sync_message(peer_a, state_a, msg_b)
+    if msg_a is None and msg_b is None:
+        break
+assert set(state_a.shared_heads) == set(heads) == set(state_b.their_heads)
+assert all(isinstance(head, automerge.ChangeHash) for head in state_b.shared_heads)
+
+# the history of a document
+audited = automerge.init({"n": 0})
+for i in range(600):
+    with automerge.transaction(audited, f"set {i}", time=1_000 + i) as d:
+        d.n = i
+        if i % 100 == 0:
+            d[f"k{i}"] = [i, i]
+entries = automerge.history(audited)
+assert entries.__length_hint__() == 601
+first = next(entries)
+assert first.seq == 1 and first.message is None and entries.__length_hint__() == 600
+rest = list(entries)
+assert len(rest) == 600 and entries.__length_hint__() == 0 and next(entries, None) is None
+assert [entry.message for entry in rest] == [f"set {i}" for i in range(600)]
+assert [entry.seq for entry in rest] == list(range(2, 602)) and rest[5].timestamp == 1_005
+changes = automerge.get_changes(audited, [])
+assert [entry.hash for entry in [first, *rest]] == [change.hash for change in changes]
+assert [entry.operations for entry in rest] == [change.len for change in changes[1:]] and rest[0].operations > rest[1].operations == 1
+assert all(entry.actor_id == automerge.get_actor(audited) for entry in rest)
+assert repr(rest[-1]) == f"HistoryEntry(hash={str(rest[-1].hash)[:7]}, actor={automerge.get_actor(audited).hex()}, seq=601, message=\"set 599\", operations=1)"
+# changes made while iterating are not part of the history
+entries = automerge.history(audited)
+with automerge.transaction(audited) as d:
+    d.n = -1
+assert len(list(entries)) == 601 and len(list(automerge.history(audited))) == 602
+
+# who last modified a key
+edited = automerge.init({"title": "draft", "rows": ["a", "b"]})
+with automerge.transaction(edited, "rename", time=5_000) as d:
+    d.title = "final"
+    d.rows[1] = "B"
+[info] = edited.modified_info("title")
+assert info.message == "rename" and info.timestamp == 5_000 and info.actor_id == automerge.get_actor(edited)
+assert info.hash == automerge.get_heads(edited)[0] and info.opid == edited.get_all("title")[0][1]
+assert repr(info) == f"ModifiedInfo(opid={info.opid}, message=\"rename\", timestamp=5000)"
+assert edited.rows.modified_info(1)[0].message == "rename" and edited.rows.modified_info(0)[0].message is None
+assert edited.modified_info("missing") == []
+try:
+    edited.rows.modified_info(5)
+    assert False
+except IndexError:
+    pass
+renamed = automerge.get_heads(edited)
+other = automerge.fork(edited)
+with automerge.transaction(edited, "mine", time=6_000) as d:
+    d.title = "mine"
+with automerge.transaction(other, "theirs", time=7_000) as d:
+    d.title = "theirs"
+automerge.merge(edited, other)
+conflicts = edited.modified_info("title")
+assert [info.opid for info in conflicts] == [opid for _, opid in edited.get_all("title")]
+assert {(info.message, info.timestamp, info.actor_id) for info in conflicts} == {
+    ("mine", 6_000, automerge.get_actor(edited)), ("theirs", 7_000, automerge.get_actor(other))}
+assert [info.message for info in automerge.at(edited, renamed).modified_info("title")] == ["rename"]
+
+# merging while other threads open transactions, merge the other way around or compare the documents
+racing_a = automerge.init({"log": []})
+for i in range(300):
+    with automerge.transaction(racing_a) as d:
+        d.counter = i
+racing_b = automerge.fork(racing_a)
+race_errors = []
+racing = True
+
+def append_to_a():
+    global racing
+    try:
+        for i in range(500):
+            with automerge.transaction(racing_a) as d:
+                d.log.append(i)
+    except Exception as e:
+        race_errors.append(e)
+    racing = False
+
+def until_done(action, doc_a, doc_b):
+    try:
+        while racing:
+            try:
+                action(doc_a, doc_b)
+            except automerge.TransactionInProgressError:
+                pass
+    except Exception as e:
+        race_errors.append(e)
+
+racers = [
+    threading.Thread(target=until_done, args=(automerge.merge, racing_a, racing_b)),
+    threading.Thread(target=until_done, args=(automerge.merge, racing_b, racing_a)),
+    threading.Thread(target=until_done, args=(automerge.get_changes_added, racing_a, racing_b)),
+    threading.Thread(target=append_to_a),
+]
+for racer in racers:
+    racer.start()
+for racer in racers:
+    racer.join(60)
+assert not any(racer.is_alive() for racer in racers) and race_errors == [], race_errors
+assert racing_a.log.to_list() == list(range(500))
+automerge.merge(racing_b, racing_a)
+assert racing_b.log.to_list() == list(range(500))
+# a merge raising because another thread opened a transaction didn't merge anything
+merge_base = automerge.init({"n": 0})
+merging = automerge.fork(merge_base)
+racing = True
+def open_transactions():
+    while racing:
+        with automerge.transaction(merging) as d:
+            d.n = d.n + 1
+opening = threading.Thread(target=open_transactions)
+opening.start()
+not_merged = []
+for i in range(300):
+    branch = automerge.fork(merge_base)
+    with automerge.transaction(branch) as d:
+        d.i = i
+    try:
+        automerge.merge(merging, branch)
+    except automerge.TransactionInProgressError:
+        not_merged.extend(automerge.get_heads(branch))
+racing = False
+opening.join(5)
+assert not_merged and all(automerge.get_change_by_hash(merging, hash) is None for hash in not_merged)